@@ -8,6 +8,9 @@ pub struct Bitmap {
 
     // whether the qr code has a designated "quiet zone"
     border: bool,
+
+    // whether light and dark have been swapped, making the quiet zone dark
+    inverted: bool,
 }
 
 impl Bitmap {
@@ -15,18 +18,27 @@ impl Bitmap {
     pub fn scale(self, target_width: Option<usize>) -> Bitmap {
         // define this code snippet as a function due to re-use
         fn bitmap_subfunction(input: Bitmap, target_width: usize) -> Bitmap {
-            let mut output = Bitmap::new(target_width, target_width);
+            // the height is scaled by the same factor, so non-square
+            // bitmaps keep their proportions
             let factor = input.width as f32 / target_width as f32;
+            let target_height = ((input.height as f32 / factor).round() as usize).max(1);
+            let mut output = Bitmap::new(target_width, target_height);
             for i in 0..target_width {
                 // horizontal step
                 let fi = (i as f32 * factor).trunc() as usize;
                 if input.border && (fi < 8 || (input.width - fi) < 8) {
+                    if input.inverted {
+                        for j in 0..target_height {
+                            output.set_bit(i, j, true);
+                        }
+                    }
                     continue;
                 }
-                for j in 0..target_width {
+                for j in 0..target_height {
                     // vertical step
-                    let fj = (j as f32 * factor).trunc() as usize;
+                    let fj = ((j as f32 * factor).trunc() as usize).min(input.height - 1);
                     if input.border && (fj < 8 || (input.height - fj) < 8) {
+                        output.set_bit(i, j, input.inverted);
                         continue;
                     }
 
@@ -35,6 +47,7 @@ impl Bitmap {
                     }
                 }
             }
+            output.inverted = input.inverted;
             output
         }
 
@@ -68,17 +81,48 @@ impl Bitmap {
             return self;
         }
         let mut output = Bitmap::new(self.width + 16, self.height + 16);
+        if self.inverted {
+            // the quiet zone is always the same color as the light modules
+            output = output.invert();
+        }
         for i in 0..self.width {
             for j in 0..self.height {
-                if self.get_bit(i, j).expect("border") {
-                    output.set_bit(i + 8, j + 8, true);
-                }
+                output.set_bit(i + 8, j + 8, self.get_bit(i, j).expect("border"));
             }
         }
         output.border = true;
         output
     }
 
+    /// Swap light and dark. This turns a QR code into a "reflectance reversed"
+    /// one, light on dark, and [add_border](Bitmap::add_border) and
+    /// [scale](Bitmap::scale) will give it a dark quiet zone to match.
+    pub fn invert(self) -> Bitmap {
+        let mut output = self.clone();
+        for x in 0..self.width {
+            for y in 0..self.height {
+                output.set_bit(x, y, !self.get_bit(x, y).unwrap());
+            }
+        }
+        output.inverted = !self.inverted;
+        output
+    }
+
+    /// Flip the bitmap over its main diagonal, swapping rows and columns.
+    /// For a QR code, this gives the mirror image that's seen through
+    /// the back of a transparent surface.
+    pub fn transpose(self) -> Bitmap {
+        let mut output = Bitmap::new(self.height, self.width);
+        for x in 0..self.width {
+            for y in 0..self.height {
+                output.set_bit(y, x, self.get_bit(x, y).unwrap());
+            }
+        }
+        output.border = self.border;
+        output.inverted = self.inverted;
+        output
+    }
+
     /// Exports the bitmap as an uncompressed black-and-white BMP file.
     pub fn as_bmp(&self) -> Vec<u8> {
         // row bytes in final pixel array
@@ -132,6 +176,21 @@ impl Bitmap {
         output
     }
 
+    /// Imports a BMP file as a black-and-white bitmap.
+    ///
+    /// Any pixel darker than middle gray is set. See [bmp_to_luma]
+    /// for the kinds of files that are supported.
+    pub fn from_bmp(data: &[u8]) -> Result<Bitmap, String> {
+        let (width, height, luma) = bmp_to_luma(data)?;
+        let mut output = Bitmap::new(width, height);
+        for (i, &value) in luma.iter().enumerate() {
+            if value < 128 {
+                output.set_bit(i % width, i / width, true);
+            }
+        }
+        Ok(output)
+    }
+
     /// `as_xbm()`, but with an added 8 pixel quiet-zone border on all sides
     pub fn as_xbm(&self, name: &str) -> String {
         assert!(
@@ -169,6 +228,169 @@ impl Bitmap {
             )
     }
 
+    /// Imports an XBM file, as written by [as_xbm](Bitmap::as_xbm).
+    ///
+    /// The name of the image is ignored, as are any other `#define`s
+    /// (such as hotspot coordinates).
+    pub fn from_xbm(text: &str) -> Result<Bitmap, String> {
+        let (mut width, mut height) = (None, None);
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("#define") {
+                continue;
+            }
+            if let (Some(name), Some(value)) = (words.next(), words.next()) {
+                let value = value.parse::<usize>().ok();
+                if name.ends_with("_width") {
+                    width = value;
+                } else if name.ends_with("_height") {
+                    height = value;
+                }
+            }
+        }
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err("XBM width or height is missing".to_string()),
+        };
+
+        // the data is everything between the braces
+        let start = text.find('{').ok_or("XBM data not found")?;
+        let end = start + text[start..].find('}').ok_or("XBM data is unterminated")?;
+        let bytes = text[start + 1..end]
+            .split(',')
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let parsed = match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => word.parse::<u8>(),
+                };
+                parsed.map_err(|_| format!("invalid XBM byte '{}'", word))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+
+        let mut output = Bitmap::new(width, height);
+        if bytes.len() != output.bits.len() {
+            return Err(format!(
+                "XBM data is {} bytes, but a {}x{} image needs {}",
+                bytes.len(),
+                width,
+                height,
+                output.bits.len()
+            ));
+        }
+        // xbm stores the leftmost pixel in the lowest bit, the other way around
+        // from the internal format. the rows are byte-aligned in both
+        for (bits, byte) in output.bits.iter_mut().zip(bytes) {
+            *bits = byte.reverse_bits();
+        }
+        Ok(output)
+    }
+
+    /// Exports the bitmap as a plain-text grid: one line per row,
+    /// with `#` for dark pixels and `.` for light ones.
+    pub fn as_text(&self) -> String {
+        let mut output = String::with_capacity((self.width + 1) * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                output.push(if self.get_bit(x, y).unwrap() {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Imports a plain-text grid, such as one written by [as_text](Bitmap::as_text).
+    ///
+    /// Dark pixels can be written as `#`, `X` or `1`, and light ones as `.`, `_`
+    /// or `0`. Whitespace is ignored, and so are blank lines, but every row
+    /// has to be the same width. Any quiet zone is kept as part of the image;
+    /// use [crop_to_content](Bitmap::crop_to_content) to get rid of it.
+    pub fn from_text(text: &str) -> Result<Bitmap, String> {
+        let mut rows: Vec<Vec<bool>> = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let row = line
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| match c {
+                    '#' | 'X' | '1' => Ok(true),
+                    '.' | '_' | '0' => Ok(false),
+                    _ => Err(format!(
+                        "line {}: unknown character '{}'",
+                        line_number + 1,
+                        c
+                    )),
+                })
+                .collect::<Result<Vec<bool>, String>>()?;
+            if row.is_empty() {
+                continue;
+            }
+            if rows.first().is_some_and(|first| first.len() != row.len()) {
+                return Err(format!(
+                    "line {}: row is {} pixels wide, but the first row is {}",
+                    line_number + 1,
+                    row.len(),
+                    rows[0].len()
+                ));
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() {
+            return Err("text grid is empty".to_string());
+        }
+
+        let mut output = Bitmap::new(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &bit) in row.iter().enumerate() {
+                output.set_bit(x, y, bit);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Imports a BMP, XBM or plain-text file, telling them apart by their contents.
+    ///
+    /// BMP files are thresholded with [GrayImage::binarize], so they don't
+    /// need to be black and white.
+    pub fn from_file_data(data: &[u8]) -> Result<Bitmap, String> {
+        if data.starts_with(b"BM") {
+            return Ok(GrayImage::from_bmp(data)?.binarize());
+        }
+        let text = std::str::from_utf8(data).map_err(|_| "not a BMP, XBM or text file")?;
+        if text.contains("#define") {
+            Bitmap::from_xbm(text)
+        } else {
+            Bitmap::from_text(text)
+        }
+    }
+
+    /// Crop the bitmap down to the smallest rectangle that contains all of its
+    /// dark pixels, removing any quiet zone. A blank bitmap is returned as-is.
+    pub fn crop_to_content(&self) -> Bitmap {
+        let dark: Vec<(usize, usize)> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.get_bit(x, y).unwrap())
+            .collect();
+        let (Some(left), Some(right)) = (
+            dark.iter().map(|p| p.0).min(),
+            dark.iter().map(|p| p.0).max(),
+        ) else {
+            return self.clone();
+        };
+        let top = dark.first().unwrap().1;
+        let bottom = dark.last().unwrap().1;
+
+        let mut output = Bitmap::new(right - left + 1, bottom - top + 1);
+        for &(x, y) in &dark {
+            output.set_bit(x - left, y - top, true);
+        }
+        output
+    }
+
     pub fn new(width: usize, height: usize) -> Self {
         let bits: Vec<u8> =
             vec![0; xy_to_index(width - 1, height - 1, width, height).unwrap().0 + 1];
@@ -182,6 +404,7 @@ impl Bitmap {
             height,
             bits,
             border: false,
+            inverted: false,
         }
     }
 
@@ -222,6 +445,176 @@ impl Bitmap {
     }
 }
 
+/// grayscale image format, one byte per pixel: 0 is black and 255 is white
+#[derive(Clone)]
+pub struct GrayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl GrayImage {
+    /// A new image, filled with white.
+    pub fn new(width: usize, height: usize) -> Self {
+        GrayImage {
+            width,
+            height,
+            pixels: vec![255; width * height],
+        }
+    }
+
+    /// Imports a BMP file of any supported color depth (see [bmp_to_luma]).
+    pub fn from_bmp(data: &[u8]) -> Result<Self, String> {
+        let (width, height, pixels) = bmp_to_luma(data)?;
+        Ok(GrayImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Converts a bitmap to grayscale, with set pixels as black.
+    pub fn from_bitmap(bitmap: &Bitmap) -> Self {
+        let (width, height) = bitmap.dims();
+        let mut output = GrayImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                if bitmap.get_bit(x, y).unwrap() {
+                    output.set(x, y, 0);
+                }
+            }
+        }
+        output
+    }
+
+    pub fn dims(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[y * self.width + x])
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u8) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        self.pixels[y * self.width + x] = value;
+        true
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Otsu's method: the global threshold that best separates
+    /// the image's pixels into two classes, dark and light.
+    ///
+    /// Pixels with values below the threshold count as dark.
+    pub fn otsu_threshold(&self) -> u8 {
+        let mut histogram = [0usize; 256];
+        for &p in &self.pixels {
+            histogram[p as usize] += 1;
+        }
+
+        let total = self.pixels.len() as f64;
+        let total_sum: f64 = (0..256).map(|i| (i * histogram[i]) as f64).sum();
+
+        // try every threshold, and pick the one with the
+        // highest variance between the two classes
+        let (mut best_threshold, mut best_variance) = (128, 0.0);
+        let (mut dark_count, mut dark_sum) = (0.0, 0.0);
+        for threshold in 1..256 {
+            dark_count += histogram[threshold - 1] as f64;
+            dark_sum += ((threshold - 1) * histogram[threshold - 1]) as f64;
+            let light_count = total - dark_count;
+            if dark_count == 0.0 || light_count == 0.0 {
+                continue;
+            }
+
+            let mean_difference = dark_sum / dark_count - (total_sum - dark_sum) / light_count;
+            let variance = dark_count * light_count * mean_difference.powi(2);
+            if variance > best_variance {
+                (best_threshold, best_variance) = (threshold, variance);
+            }
+        }
+        best_threshold as u8
+    }
+
+    /// Convert to black and white with a single global threshold
+    /// (see [otsu_threshold](GrayImage::otsu_threshold)).
+    pub fn binarize(&self) -> Bitmap {
+        let threshold = self.otsu_threshold();
+        let mut output = Bitmap::new(self.width, self.height);
+        for (i, &p) in self.pixels.iter().enumerate() {
+            if p < threshold {
+                output.set_bit(i % self.width, i / self.width, true);
+            }
+        }
+        output
+    }
+
+    /// Convert to black and white with a threshold that adapts to the
+    /// surroundings of each pixel, for images with uneven lighting.
+    ///
+    /// A pixel counts as dark if it's darker than the average of the square
+    /// window of `2 * radius + 1` pixels around it. Windows with too little
+    /// contrast to say anything (e.g. the inside of a large dark area) fall
+    /// back to the global threshold from [otsu_threshold](GrayImage::otsu_threshold).
+    /// For QR codes, the window should be a few modules wide.
+    pub fn binarize_adaptive(&self, radius: usize) -> Bitmap {
+        let (w, h) = (self.width, self.height);
+
+        // summed-area tables of values and squared values,
+        // with an extra row and column of zeros at the start
+        let mut sums = vec![0u64; (w + 1) * (h + 1)];
+        let mut squares = vec![0u64; (w + 1) * (h + 1)];
+        for y in 0..h {
+            let (mut row_sum, mut row_squares) = (0, 0);
+            for x in 0..w {
+                let p = self.pixels[y * w + x] as u64;
+                row_sum += p;
+                row_squares += p * p;
+                sums[(y + 1) * (w + 1) + x + 1] = sums[y * (w + 1) + x + 1] + row_sum;
+                squares[(y + 1) * (w + 1) + x + 1] = squares[y * (w + 1) + x + 1] + row_squares;
+            }
+        }
+        let area_sum = |table: &[u64], x0: usize, y0: usize, x1: usize, y1: usize| {
+            table[y1 * (w + 1) + x1] + table[y0 * (w + 1) + x0]
+                - table[y0 * (w + 1) + x1]
+                - table[y1 * (w + 1) + x0]
+        };
+
+        // the minimum standard deviation for a window to count as having contrast
+        const MIN_DEVIATION: f64 = 12.0;
+
+        let global = self.otsu_threshold() as f64;
+        let mut output = Bitmap::new(w, h);
+        for y in 0..h {
+            let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(h));
+            for x in 0..w {
+                let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(w));
+                let count = ((x1 - x0) * (y1 - y0)) as f64;
+                let mean = area_sum(&sums, x0, y0, x1, y1) as f64 / count;
+                let variance = area_sum(&squares, x0, y0, x1, y1) as f64 / count - mean * mean;
+
+                let threshold = if variance.max(0.0).sqrt() < MIN_DEVIATION {
+                    global
+                } else {
+                    mean
+                };
+                if (self.pixels[y * w + x] as f64) < threshold {
+                    output.set_bit(x, y, true);
+                }
+            }
+        }
+        output
+    }
+}
+
 /// converts xy coordinates to the pixel's vector/bit indices:
 /// `(n, i)` = bit `i` of `vec[n]`.
 /// returns None when coords are out of bounds.
@@ -253,3 +646,353 @@ pub fn index_to_xy(vec_index: usize, bit_index: u8, w: usize, h: usize) -> Optio
     }
     Some((x, y))
 }
+
+/// Reads the pixels of a BMP file as luminance values, from 0 (black) to 255 (white).
+///
+/// Returns the width, the height, and the pixels row by row from the top.
+/// Uncompressed files with a `BITMAPINFOHEADER` (or one of its later, longer
+/// versions) are supported, at 1 or 8 bits per pixel with a palette, or at
+/// 24 or 32 bits per pixel, stored either bottom-up or top-down.
+pub fn bmp_to_luma(data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    let read = |offset: usize, length: usize| -> Result<u32, String> {
+        let bytes = data
+            .get(offset..offset + length)
+            .ok_or("BMP file is truncated")?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, &b| (acc << 8) | u32::from(b)))
+    };
+
+    if data.get(0..2) != Some(b"BM") {
+        return Err("not a BMP file".to_string());
+    }
+    let data_offset = read(0x0a, 4)? as usize;
+    let header_size = read(0x0e, 4)? as usize;
+    if header_size < 40 {
+        return Err(format!("unsupported BMP header of {} bytes", header_size));
+    }
+
+    let width = read(0x12, 4)? as i32;
+    let height = read(0x16, 4)? as i32;
+    let bits_per_pixel = read(0x1c, 2)?;
+    let compression = read(0x1e, 4)?;
+    let colors_used = read(0x2e, 4)? as usize;
+
+    if width <= 0 || height == 0 {
+        return Err(format!("invalid BMP dimensions {}x{}", width, height));
+    }
+    // a negative height means the rows are stored from the top down
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+    // 32-bit images may have their channels rearranged by bit masks,
+    // which either follow the header or are part of it
+    let channel_masks = match (compression, bits_per_pixel) {
+        (0, _) => [0xff_0000, 0xff00, 0xff],
+        (3, 32) => [read(0x36, 4)?, read(0x3a, 4)?, read(0x3e, 4)?],
+        _ => {
+            return Err(format!(
+                "unsupported BMP compression {} at {} bits per pixel",
+                compression, bits_per_pixel
+            ))
+        }
+    };
+
+    // luminance approximation with integer weights (ITU-R BT.601)
+    let luma = |r: u32, g: u32, b: u32| ((299 * r + 587 * g + 114 * b) / 1000) as u8;
+
+    let palette: Vec<u8> = match bits_per_pixel {
+        1 | 8 => {
+            let count = if colors_used == 0 {
+                1 << bits_per_pixel
+            } else {
+                colors_used
+            };
+            let start = 0x0e + header_size;
+            (0..count)
+                .map(|i| {
+                    // palette entries are stored as blue, green, red, unused
+                    let entry = read(start + 4 * i, 4)?;
+                    Ok(luma(
+                        (entry >> 16) & 0xff,
+                        (entry >> 8) & 0xff,
+                        entry & 0xff,
+                    ))
+                })
+                .collect::<Result<_, String>>()?
+        }
+        24 | 32 => vec![],
+        _ => {
+            return Err(format!(
+                "unsupported BMP color depth of {} bits",
+                bits_per_pixel
+            ))
+        }
+    };
+
+    // rows are padded to a multiple of 4 bytes
+    let row_bits = width
+        .checked_mul(bits_per_pixel as usize)
+        .ok_or("BMP file is truncated")?;
+    let row_bytes = row_bits.div_ceil(32) * 4;
+
+    // the header can claim up to 2^31 by 2^31 pixels, so make sure
+    // they're all in the file before allocating anything for them
+    // (the last row doesn't need its padding)
+    let pixels_end = row_bytes
+        .checked_mul(height - 1)
+        .and_then(|n| n.checked_add(row_bits.div_ceil(8)))
+        .and_then(|n| n.checked_add(data_offset));
+    if pixels_end.is_none_or(|end| end > data.len()) {
+        return Err("BMP file is truncated".to_string());
+    }
+    let mut output = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row_start = data_offset + row * row_bytes;
+
+        for x in 0..width {
+            output.push(match bits_per_pixel {
+                1 => {
+                    let byte = read(row_start + x / 8, 1)?;
+                    let index = (byte >> (7 - x % 8)) & 1;
+                    *palette
+                        .get(index as usize)
+                        .ok_or("BMP palette is too short")?
+                }
+                8 => {
+                    let index = read(row_start + x, 1)?;
+                    *palette
+                        .get(index as usize)
+                        .ok_or("BMP palette is too short")?
+                }
+                24 => {
+                    let pixel = read(row_start + 3 * x, 3)?;
+                    luma((pixel >> 16) & 0xff, (pixel >> 8) & 0xff, pixel & 0xff)
+                }
+                _ => {
+                    let pixel = read(row_start + 4 * x, 4)?;
+                    let [r, g, b] = channel_masks.map(|mask| {
+                        // scale the channel to 8 bits, whatever its width
+                        if mask == 0 {
+                            0
+                        } else {
+                            let value = (pixel & mask) >> mask.trailing_zeros();
+                            value * 255 / (mask >> mask.trailing_zeros())
+                        }
+                    });
+                    luma(r, g, b)
+                }
+            });
+        }
+    }
+
+    Ok((width, height, output))
+}
+
+#[test]
+fn bmp_round_trip() -> Result<(), String> {
+    let mut bitmap = Bitmap::new(37, 21);
+    for i in 0..37 {
+        bitmap.set_bit(i, (i * 7) % 21, true);
+    }
+    let compare = |other: &Bitmap| {
+        other.dims() == bitmap.dims()
+            && (0..37).all(|x| (0..21).all(|y| other.get_bit(x, y) == bitmap.get_bit(x, y)))
+    };
+
+    if !compare(&Bitmap::from_bmp(&bitmap.as_bmp())?) {
+        return Err("1-bit BMP was read incorrectly".to_string());
+    }
+
+    // re-encode as a top-down 24-bit file
+    let mut bmp = bitmap.as_bmp()[..0x3e].to_vec();
+    bmp[0x16..0x1a].copy_from_slice(&(-21i32).to_le_bytes());
+    bmp[0x1c] = 24;
+    bmp.truncate(0x36);
+    bmp[0x0a] = 0x36;
+    for y in 0..21 {
+        for x in 0..37 {
+            let value = if bitmap.get_bit(x, y) == Some(true) {
+                0x10
+            } else {
+                0xf0
+            };
+            bmp.extend([value; 3]);
+        }
+        // 111 bytes of pixels, padded to 112
+        bmp.push(0);
+    }
+    if !compare(&Bitmap::from_bmp(&bmp)?) {
+        return Err("24-bit BMP was read incorrectly".to_string());
+    }
+
+    // as_bmp() makes palette index 0 white, so swap the palette around
+    // and flip every bit to get a file where index 0 is black
+    let mut bmp = bitmap.as_bmp();
+    bmp[0x36..0x3e].copy_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0]);
+    for byte in &mut bmp[0x3e..] {
+        *byte = !*byte;
+    }
+    if !compare(&Bitmap::from_bmp(&bmp)?) {
+        return Err("1-bit BMP with index 0 black was read incorrectly".to_string());
+    }
+
+    // a header for the same picture at another depth, with its pixels
+    // starting after the given number of palette or mask bytes
+    let header = |bits: u8, compression: u8, extra: usize| {
+        let mut bmp = bitmap.as_bmp()[..0x36].to_vec();
+        bmp[0x0a] = (0x36 + extra) as u8;
+        bmp[0x1c] = bits;
+        bmp[0x1e] = compression;
+        bmp[0x2e] = 0;
+        bmp
+    };
+    // rows of pixels from the bottom up, each padded to 4 bytes
+    let rows = |bmp: &mut Vec<u8>, pixel: &dyn Fn(bool) -> Vec<u8>| {
+        for y in (0..21).rev() {
+            let start = bmp.len();
+            for x in 0..37 {
+                bmp.extend(pixel(bitmap.get_bit(x, y) == Some(true)));
+            }
+            bmp.resize(start + (bmp.len() - start).div_ceil(4) * 4, 0);
+        }
+    };
+
+    // 8-bit, with a three-color palette: light blue, red and dark gray
+    let mut bmp = header(8, 0, 12);
+    bmp[0x2e] = 3;
+    bmp.extend([
+        0xf0, 0xd0, 0xc0, 0, 0x20, 0x20, 0xe0, 0, 0x30, 0x30, 0x30, 0,
+    ]);
+    rows(&mut bmp, &|set| vec![if set { 2 } else { 0 }]);
+    if !compare(&Bitmap::from_bmp(&bmp)?) {
+        return Err("8-bit BMP was read incorrectly".to_string());
+    }
+
+    // 32-bit, as blue, green, red and an unused byte
+    let mut bmp = header(32, 0, 0);
+    rows(&mut bmp, &|set| {
+        if set {
+            vec![0x10, 0x20, 0x18, 0xff]
+        } else {
+            vec![0xe0, 0xf0, 0xe8, 0]
+        }
+    });
+    if !compare(&Bitmap::from_bmp(&bmp)?) {
+        return Err("32-bit BMP was read incorrectly".to_string());
+    }
+
+    // 32-bit with 10-bit channels, red in the lowest bits and blue in the
+    // highest. read with the default masks, light pixels would come out dark
+    let mut bmp = header(32, 3, 12);
+    for mask in [0x3ffu32, 0xffc00, 0x3ff0_0000] {
+        bmp.extend(mask.to_le_bytes());
+    }
+    rows(&mut bmp, &|set| {
+        let value: u32 = if set { 0x040 } else { 0x3c0 };
+        (value | value << 10 | value << 20).to_le_bytes().to_vec()
+    });
+    if !compare(&Bitmap::from_bmp(&bmp)?) {
+        return Err("32-bit BMP with bit masks was read incorrectly".to_string());
+    }
+
+    // a header claiming huge dimensions, with no pixels behind it
+    let mut bmp = bitmap.as_bmp()[..0x3e].to_vec();
+    for (width, bits) in [(0x7fff_ffffi32, 1u8), (0x7fff_ffff, 32), (37, 32)] {
+        bmp[0x12..0x16].copy_from_slice(&width.to_le_bytes());
+        bmp[0x16..0x1a].copy_from_slice(&0x7fff_ffffi32.to_le_bytes());
+        bmp[0x1c] = bits;
+        if Bitmap::from_bmp(&bmp).is_ok() {
+            return Err(format!("{}-bit BMP with no pixels was accepted", bits));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn xbm_round_trip() -> Result<(), String> {
+    let mut bitmap = Bitmap::new(21, 13);
+    for i in 0..21 {
+        bitmap.set_bit(i, (i * 5) % 13, true);
+    }
+    let read = Bitmap::from_xbm(&bitmap.as_xbm("test"))?;
+    if read.dims() != bitmap.dims() || read.bits != bitmap.bits {
+        return Err("XBM was read incorrectly".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn non_square_scaling() -> Result<(), String> {
+    // a 3:1 checkerboard of 1-pixel squares, with a quiet zone
+    let mut bitmap = Bitmap::new(27, 9);
+    for x in 0..27 {
+        for y in 0..9 {
+            bitmap.set_bit(x, y, (x + y) % 2 == 0);
+        }
+    }
+    let scaled = bitmap.add_border().scale(Some(430));
+    if scaled.dims() != (430, 250) {
+        return Err(format!("scaled to {:?}", scaled.dims()));
+    }
+    // every pixel lands in the right square: 10 pixels per module,
+    // with 80 pixels of quiet zone
+    for x in 0..430 {
+        for y in 0..250 {
+            let (mx, my) = (x / 10, y / 10);
+            let expected = (8..35).contains(&mx) && (8..17).contains(&my) && (mx + my) % 2 == 0;
+            if scaled.get_bit(x, y) != Some(expected) {
+                return Err(format!("pixel ({}, {}) is wrong", x, y));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn text_round_trip() -> Result<(), String> {
+    let grid = "
+        . . . . . . .
+        . # # . # # .
+        . # . . . # .
+        . # # # # # .
+        . . . . . . .
+    ";
+    let bitmap = Bitmap::from_text(grid)?;
+    let text = bitmap.as_text();
+    if bitmap.dims() != (7, 5) || text.lines().nth(2) != Some(".#...#.") {
+        return Err(format!("text grid was read incorrectly:\n{}", text));
+    }
+    if Bitmap::from_text(&text)?.bits != bitmap.bits {
+        return Err("text grid did not round-trip".to_string());
+    }
+    if bitmap.crop_to_content().dims() != (5, 3) {
+        return Err("quiet zone was not cropped".to_string());
+    }
+    Ok(())
+}
+
+// a checkerboard in a strong lighting gradient: the global
+// threshold fails on it, but the adaptive one shouldn't
+#[test]
+fn adaptive_binarization() -> Result<(), String> {
+    let mut image = GrayImage::new(120, 40);
+    for y in 0..40 {
+        for x in 0..120 {
+            let light = 60 + x as u8 + if (x / 4 + y / 4) % 2 == 0 { 60 } else { 0 };
+            image.set(x, y, light);
+        }
+    }
+    let bitmap = image.binarize_adaptive(6);
+    for y in 0..40 {
+        for x in 0..120 {
+            if bitmap.get_bit(x, y) != Some((x / 4 + y / 4) % 2 == 1) {
+                return Err(format!("pixel ({}, {}) was binarized incorrectly", x, y));
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,4 +2,5 @@
 
 pub mod image;
 pub mod qr_standard;
+pub(crate) mod random;
 pub mod rdsm;
@@ -1,8 +1,20 @@
 use qr::qr_standard;
 
 fn main() -> std::io::Result<()> {
+    let mut args = std::env::args();
+    args.next();
+    match args.next().as_deref() {
+        Some("decode") => return decode(args),
+        Some("damage") => return damage(args),
+        Some("grade") => return grade(args),
+        _ => {}
+    }
+
     {
-        use qr_standard::{badstream::QRInput, bitstream::Mode};
+        use qr_standard::{
+            badstream::QRInput,
+            bitstream::{Fnc1, Mode},
+        };
 
         let mut input_choice: Option<QRInput> = None;
         let mut level_choice: Option<u8> = None;
@@ -10,8 +22,16 @@ fn main() -> std::io::Result<()> {
         let mut name_choice: Option<String> = None;
         let mut scale_choice: Option<usize> = None;
         let mut version_choice: Option<u32> = None;
+        let mut max_version_choice: Option<u32> = None;
         let mut stdin_choice = false;
         let mut xbm_choice = false;
+        let mut invert_choice = false;
+        let mut mirror_choice = false;
+        let mut fnc1_choice: Option<Fnc1> = None;
+        let mut micro_choice = false;
+        let mut rmqr_choice = false;
+        let mut height_choice: Option<usize> = None;
+        let mut width_choice: Option<usize> = None;
 
         let mut mode_data = Vec::new();
         let mut manual = true;
@@ -44,6 +64,10 @@ fn main() -> std::io::Result<()> {
                             let ascii_string = args.next().expect("no data for ASCII mode");
                             mode_data.push((Mode::ASCII, ascii_string));
                         }
+                        "--kanji" | "-kan" => {
+                            let kanji_string = args.next().expect("no data for kanji mode");
+                            mode_data.push((Mode::Kanji, kanji_string));
+                        }
                         _ => {
                             if argument.starts_with('-') {
                                 assert!(!mode_data.is_empty(), "manual input not specified");
@@ -109,6 +133,18 @@ fn main() -> std::io::Result<()> {
                         panic!("can't specify version twice")
                     }
                 }
+                "--max-version" => {
+                    if max_version_choice.is_none() {
+                        max_version_choice = Some(
+                            args.next()
+                                .expect("no version submitted")
+                                .parse::<u32>()
+                                .expect("invalid version"),
+                        );
+                    } else {
+                        panic!("can't specify maximum version twice")
+                    }
+                }
                 "--mask" | "-m" => {
                     if mask_choice.is_none() {
                         mask_choice = Some(
@@ -140,6 +176,74 @@ fn main() -> std::io::Result<()> {
                         panic!("can't specify XBM output twice")
                     }
                 }
+                "--invert" => {
+                    if !invert_choice {
+                        invert_choice = true;
+                    } else {
+                        panic!("can't specify inversion twice")
+                    }
+                }
+                "--mirror" => {
+                    if !mirror_choice {
+                        mirror_choice = true;
+                    } else {
+                        panic!("can't specify mirroring twice")
+                    }
+                }
+                "--micro" => {
+                    if !micro_choice {
+                        micro_choice = true;
+                    } else {
+                        panic!("can't specify Micro QR twice")
+                    }
+                }
+                "--rmqr" => {
+                    if !rmqr_choice {
+                        rmqr_choice = true;
+                    } else {
+                        panic!("can't specify rMQR twice")
+                    }
+                }
+                "--height" => {
+                    if height_choice.is_none() {
+                        height_choice = Some(
+                            args.next()
+                                .expect("no height submitted")
+                                .parse::<usize>()
+                                .expect("invalid height"),
+                        );
+                    } else {
+                        panic!("can't specify height twice")
+                    }
+                }
+                "--width" => {
+                    if width_choice.is_none() {
+                        width_choice = Some(
+                            args.next()
+                                .expect("no width submitted")
+                                .parse::<usize>()
+                                .expect("invalid width"),
+                        );
+                    } else {
+                        panic!("can't specify width twice")
+                    }
+                }
+                "--gs1" | "--fnc1" => {
+                    assert!(fnc1_choice.is_none(), "can't specify FNC1 mode twice");
+                    fnc1_choice = Some(if argument == "--gs1" {
+                        Fnc1::First
+                    } else {
+                        // an application indicator: two digits, or one letter
+                        let indicator = args.next().expect("no application indicator submitted");
+                        match *indicator.as_bytes() {
+                            [a, b] if a.is_ascii_digit() && b.is_ascii_digit() => {
+                                Fnc1::Second(10 * (a - b'0') + (b - b'0'))
+                            }
+                            [letter] if letter.is_ascii_alphabetic() => Fnc1::Second(letter + 100),
+                            _ => panic!("{} - invalid application indicator", indicator),
+                        }
+                    });
+                }
                 "--" => {
                     if !stdin_choice {
                         stdin_choice = true;
@@ -154,12 +258,49 @@ fn main() -> std::io::Result<()> {
             }
         }
 
-        if let Some(v) = version_choice {
+        if let Some(v) = version_choice.or(max_version_choice) {
             assert!((1..=40).contains(&v), "version must be one of 1, ..., 40");
         }
+        assert!(
+            version_choice.is_none() || max_version_choice.is_none(),
+            "can't combine a version with a maximum version"
+        );
         if let Some(m) = mask_choice {
             assert!((0..=7).contains(&m), "mask must be one of 0, ..., 7");
         }
+        assert!(
+            !(micro_choice && rmqr_choice),
+            "can't make a Micro QR code and an rMQR code at once"
+        );
+        assert!(
+            rmqr_choice || (height_choice.is_none() && width_choice.is_none()),
+            "height and width only apply to rMQR codes"
+        );
+        if rmqr_choice {
+            assert!(
+                version_choice.is_none() && max_version_choice.is_none(),
+                "rMQR codes are chosen by height and width instead of version"
+            );
+            assert!(mask_choice.is_none(), "rMQR codes only have one mask");
+        }
+        if micro_choice {
+            assert!(
+                max_version_choice.is_none(),
+                "Micro QR codes can't be split over several codes"
+            );
+            if let Some(v) = version_choice {
+                assert!(
+                    (1..=4).contains(&v),
+                    "Micro QR version must be one of 1, ..., 4"
+                );
+            }
+            if let Some(m) = mask_choice {
+                assert!(
+                    (0..=3).contains(&m),
+                    "Micro QR mask must be one of 0, ..., 3"
+                );
+            }
+        }
 
         let mut example = false;
         let input = match input_choice {
@@ -186,24 +327,434 @@ fn main() -> std::io::Result<()> {
             }
         };
 
+        let input = match fnc1_choice {
+            Some(fnc1) => QRInput::Fnc1(fnc1, Box::new(input)),
+            None => input,
+        };
+
         let name = name_choice.unwrap_or(if example { "hello" } else { "out" }.to_string());
 
-        let qrc = qr_standard::badstream::make_qr(input, version_choice, level_choice, mask_choice)
-            .add_border()
-            .scale(scale_choice);
+        // without a fixed version, messages that are too long for one code
+        // are split over several
+        let codes = match version_choice {
+            _ if rmqr_choice => {
+                vec![
+                    qr_standard::rmqr::make_rmqr(input, height_choice, width_choice, level_choice)
+                        .map_err(std::io::Error::other)?,
+                ]
+            }
+            _ if micro_choice => vec![qr_standard::micro::make_micro_qr(
+                input,
+                version_choice,
+                level_choice,
+                mask_choice,
+            )
+            .map_err(std::io::Error::other)?],
+            Some(_) => vec![qr_standard::badstream::make_qr(
+                input,
+                version_choice,
+                level_choice,
+                mask_choice,
+            )],
+            None => qr_standard::badstream::make_structured_append(
+                input,
+                max_version_choice,
+                level_choice,
+                mask_choice,
+            )
+            .map_err(std::io::Error::other)?,
+        };
+
+        let count = codes.len();
+        for (i, mut qrc) in codes.into_iter().enumerate() {
+            if mirror_choice {
+                qrc = qrc.transpose();
+            }
+            if invert_choice {
+                qrc = qrc.invert();
+            }
+            let qrc = qrc.add_border().scale(scale_choice);
+
+            // name.bmp, or name-1.bmp, name-2.bmp, ... for a sequence
+            let name = if count > 1 {
+                format!("{}-{}", name, i + 1)
+            } else {
+                name.clone()
+            };
+            let (output, ext) = if xbm_choice {
+                (qrc.as_xbm(&name).into_bytes(), "xbm")
+            } else {
+                (qrc.as_bmp(), "bmp")
+            };
+
+            std::fs::write(format!("{}.{ext}", name), output)?;
+            println!("Wrote '{name}.{ext}' successfully.")
+        }
+        Ok(())
+    }
+}
+
+// the "qr decode" subcommand
+fn decode(args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    use qr_standard::{
+        bitstream::{Fnc1, Mode},
+        detect,
+    };
+    use std::io::Read;
+
+    let mut file_choice: Option<String> = None;
+    let mut verbose = false;
+    let mut raw = false;
+    for argument in args {
+        match argument.as_str() {
+            "--verbose" | "-V" => verbose = true,
+            "--raw" => raw = true,
+            "--help" | "-h" => {
+                println!("{}", interface::HELPTEXT);
+                return Ok(());
+            }
+            // a lone "-" is stdin, not an option
+            _ if argument.starts_with('-') && argument != "-" => {
+                panic!("{} - incorrect argument", argument)
+            }
+            _ => {
+                if file_choice.is_none() {
+                    file_choice = Some(argument);
+                } else {
+                    panic!("{} - can't decode more than one file", argument)
+                }
+            }
+        }
+    }
+
+    // no file name (or "-") reads the file from stdin
+    let data = match file_choice.as_deref() {
+        None | Some("-") => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            data
+        }
+        Some(name) => std::fs::read(name)?,
+    };
+    let image = qr::image::Bitmap::from_file_data(&data).map_err(std::io::Error::other)?;
+
+    let mut results: Vec<_> = detect::find_symbols(&image)
+        .into_iter()
+        .map(|symbol| (Some(symbol.bounds), symbol.decoded))
+        .collect();
+    if results.is_empty() {
+        // a bare grid of modules, without a quiet zone to find it by
+        results.push((None, image.crop_to_content().qr_decode()));
+    }
+
+    let mut failures = 0;
+    for (i, (bounds, decoded)) in results.iter().enumerate() {
+        if verbose || results.len() > 1 {
+            match bounds {
+                Some((left, top, right, bottom)) => println!(
+                    "symbol {} at ({}, {})-({}, {}):",
+                    i + 1,
+                    left,
+                    top,
+                    right,
+                    bottom
+                ),
+                None => println!("symbol {}:", i + 1),
+            }
+        }
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                eprintln!("Couldn't decode: {}", error);
+                failures += 1;
+                continue;
+            }
+        };
 
-        let (output, ext) = if xbm_choice {
-            (qrc.as_xbm(&name).into_bytes(), "xbm")
+        if verbose {
+            println!(
+                "    version {}, level {}, mask {}{}{}",
+                decoded.version,
+                ["L", "M", "Q", "H"][decoded.level as usize],
+                decoded.mask,
+                if decoded.mirrored { ", mirrored" } else { "" },
+                if decoded.inverted {
+                    ", light on dark"
+                } else {
+                    ""
+                },
+            );
+            match decoded.fnc1() {
+                Some(Fnc1::First) => println!("    FNC1 first position (GS1)"),
+                Some(Fnc1::Second(indicator)) => {
+                    println!(
+                        "    FNC1 second position, application indicator {}",
+                        indicator
+                    )
+                }
+                None => {}
+            }
+            if let Some(header) = decoded.structured_append {
+                println!(
+                    "    part {} of {}, parity {:02X}",
+                    header.index + 1,
+                    header.total,
+                    header.parity
+                );
+            }
+            for segment in &decoded.segments {
+                let mode = match segment.mode {
+                    Mode::Numeric => "numeric",
+                    Mode::AlphaNum => "alphanumeric",
+                    Mode::ASCII => "byte",
+                    Mode::Kanji => "kanji",
+                };
+                match segment.eci {
+                    Some(eci) => println!(
+                        "    {} segment, {} bytes, ECI {}",
+                        mode,
+                        segment.data.len(),
+                        eci
+                    ),
+                    None => println!("    {} segment, {} bytes", mode, segment.data.len()),
+                }
+            }
+            let health = &decoded.health;
+            println!(
+                "    corrected {} errors and {} erasures in {} blocks, margin {} codewords",
+                health.blocks.iter().map(|b| b.errors).sum::<usize>(),
+                health.blocks.iter().map(|b| b.erasures).sum::<usize>(),
+                health.blocks.len(),
+                health.margin()
+            );
+        }
+        // links, contact cards etc. are described rather than printed as is
+        if raw {
+            println!("{}", decoded.text());
         } else {
-            (qrc.as_bmp(), "bmp")
+            println!("{}", decoded.payload());
+        }
+    }
+
+    // the codes of a structured append sequence are also put together
+    let parts: Vec<_> = results
+        .iter()
+        .filter_map(|(_, decoded)| decoded.as_ref().ok())
+        .filter(|decoded| decoded.structured_append.is_some())
+        .cloned()
+        .collect();
+    if parts.len() > 1 {
+        match qr_standard::decode::join_structured_append(&parts) {
+            Ok(text) if raw => println!("joined:\n{}", text),
+            Ok(text) => println!("joined:\n{}", qr_standard::payload::parse(&text)),
+            Err(error) => eprintln!("Couldn't join the codes: {}", error),
+        }
+    }
+
+    if failures == results.len() {
+        Err(std::io::Error::other("no QR codes could be decoded"))
+    } else {
+        Ok(())
+    }
+}
+
+// the "qr grade" subcommand
+fn grade(args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    use qr::image::{Bitmap, GrayImage};
+    use std::io::Read;
+
+    let mut file_choice: Option<String> = None;
+    for argument in args {
+        if file_choice.is_none() {
+            file_choice = Some(argument);
+        } else {
+            panic!("{} - can't grade more than one file", argument)
+        }
+    }
+    let data = match file_choice.as_deref() {
+        None | Some("-") => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            data
+        }
+        Some(name) => std::fs::read(name)?,
+    };
+
+    // keep the gray levels of BMP files, since contrast is part of the grade
+    let image = if data.starts_with(b"BM") {
+        GrayImage::from_bmp(&data)
+    } else {
+        Bitmap::from_file_data(&data).map(|bitmap| GrayImage::from_bitmap(&bitmap))
+    }
+    .map_err(std::io::Error::other)?;
+    let report = qr_standard::quality::grade(&image).map_err(std::io::Error::other)?;
+
+    println!("decode:                   {}", report.decode);
+    println!(
+        "symbol contrast:          {} ({:.0}%)",
+        report.symbol_contrast_grade,
+        report.symbol_contrast * 100.0
+    );
+    println!("modulation:               {}", report.modulation_grade);
+    println!(
+        "fixed pattern damage:     {}",
+        report.fixed_pattern_damage_grade
+    );
+    println!(
+        "axial non-uniformity:     {} ({:.3})",
+        report.axial_nonuniformity_grade, report.axial_nonuniformity
+    );
+    println!(
+        "grid non-uniformity:      {} ({:.2} modules)",
+        report.grid_nonuniformity_grade, report.grid_nonuniformity
+    );
+    println!(
+        "unused error correction:  {} ({:.0}%)",
+        report.unused_error_correction_grade,
+        report.unused_error_correction * 100.0
+    );
+    println!("overall:                  {}", report.overall);
+    Ok(())
+}
+
+// the "qr damage" subcommand
+fn damage(mut args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    use qr_standard::damage::{simulate, Corner, Damage};
+
+    // comma-separated numbers, optionally followed by "dark" or "light"
+    fn numbers(argument: Option<String>, count: usize) -> (Vec<f32>, bool) {
+        let argument = argument.expect("no damage parameters submitted");
+        let mut parts: Vec<&str> = argument.split(',').map(str::trim).collect();
+        let dark = match parts.last() {
+            Some(&"light") => {
+                parts.pop();
+                false
+            }
+            Some(&"dark") => {
+                parts.pop();
+                true
+            }
+            _ => true,
         };
+        let values: Vec<f32> = parts
+            .iter()
+            .map(|part| part.parse().expect("can't parse damage parameter"))
+            .collect();
+        assert!(
+            values.len() == count,
+            "{} - expected {} numbers",
+            argument,
+            count
+        );
+        (values, dark)
+    }
 
-        let write_status = std::fs::write(format!("{}.{ext}", name), output);
-        if write_status.is_ok() {
-            println!("Wrote '{name}.{ext}' successfully.")
+    let mut file_choice: Option<String> = None;
+    let mut name_choice: Option<String> = None;
+    let mut seed = 0;
+    let mut damage = Vec::new();
+    while let Some(argument) = args.next() {
+        match argument.as_str() {
+            "--noise" => {
+                let (values, _) = numbers(args.next(), 1);
+                damage.push(Damage::Noise { rate: values[0] });
+            }
+            "--rect" => {
+                let (values, dark) = numbers(args.next(), 4);
+                damage.push(Damage::Rectangle {
+                    x: values[0],
+                    y: values[1],
+                    width: values[2],
+                    height: values[3],
+                    dark,
+                });
+            }
+            "--circle" => {
+                let (values, dark) = numbers(args.next(), 3);
+                damage.push(Damage::Circle {
+                    x: values[0],
+                    y: values[1],
+                    radius: values[2],
+                    dark,
+                });
+            }
+            "--scratch" => {
+                let (values, dark) = numbers(args.next(), 5);
+                damage.push(Damage::Scratch {
+                    from: (values[0], values[1]),
+                    to: (values[2], values[3]),
+                    width: values[4],
+                    dark,
+                });
+            }
+            "--corner" => {
+                let argument = args.next().expect("no corner submitted");
+                let (corner, size) = argument.split_once(',').expect("corner needs a size");
+                let corner = match corner.trim().to_ascii_lowercase().as_str() {
+                    "tl" => Corner::TopLeft,
+                    "tr" => Corner::TopRight,
+                    "bl" => Corner::BottomLeft,
+                    "br" => Corner::BottomRight,
+                    _ => panic!("{} - invalid corner", corner),
+                };
+                let size = size.trim().parse().expect("can't parse corner size");
+                damage.push(Damage::Corner { corner, size });
+            }
+            "--seed" => {
+                seed = args
+                    .next()
+                    .expect("no seed submitted")
+                    .parse()
+                    .expect("invalid seed");
+            }
+            "--name" | "-n" => {
+                if name_choice.is_none() {
+                    name_choice = Some(args.next().expect("no name submitted"));
+                } else {
+                    panic!("can't specify name twice")
+                }
+            }
+            _ => {
+                if file_choice.is_none() {
+                    file_choice = Some(argument);
+                } else {
+                    panic!("{} - incorrect argument", argument)
+                }
+            }
         }
-        write_status
     }
+
+    let data = std::fs::read(file_choice.expect("no file to damage"))?;
+    let image = qr::image::Bitmap::from_file_data(&data).map_err(std::io::Error::other)?;
+    // damage is done to the modules, not the pixels
+    let code = qr_standard::detect::sample_qr(&image).unwrap_or_else(|_| image.crop_to_content());
+
+    let report = simulate(&code, &damage, seed);
+    println!("{} modules damaged", report.changed_modules);
+    match &report.decoded {
+        Ok(decoded) => {
+            println!("Still readable: {}", decoded.text());
+            for (i, block) in decoded.health.blocks.iter().enumerate() {
+                println!(
+                    "    block {}: {} errors, can take {} more",
+                    i,
+                    block.errors,
+                    block.margin() / 2
+                );
+            }
+        }
+        Err(error) => println!("Unreadable: {}", error),
+    }
+
+    let name = name_choice.unwrap_or("damaged".to_string());
+    let write_status = std::fs::write(
+        format!("{}.bmp", name),
+        report.damaged.add_border().scale(None).as_bmp(),
+    );
+    if write_status.is_ok() {
+        println!("Wrote '{name}.bmp' successfully.")
+    }
+    write_status
 }
 
 // returns a description of inputs that will lead make_qr() to panic
@@ -251,12 +802,12 @@ mod interface {
         let mut output = EXAMPLE_MESSAGE.to_string();
         if let Ok(epoch_secs) = SystemTime::now().duration_since(UNIX_EPOCH) {
             let secs = epoch_secs.as_millis();
-            let hour = (secs % 86400_000) / 3600_000;
-            let minute = (secs % 3600_000) / 60_000;
+            let hour = (secs % 86_400_000) / 3_600_000;
+            let minute = (secs % 3_600_000) / 60_000;
             let second = (secs % 60_000) / 1_000;
 
             // swatch internet time, adjusted to UTC+1
-            let beats = ((secs + 3600_000) % 86400_000) / 86_400;
+            let beats = ((secs + 3_600_000) % 86_400_000) / 86_400;
             output.push_str(&format!(
                 "\n{:02}:{:02}:{:02} UTC @{:03}",
                 hour, minute, second, beats
@@ -275,25 +826,54 @@ by esmeralda (cool-es)
     manual:     qr -i --manual -asc \"Hello! \" -aln \"HELLO. \" -num \"07734\"
     (automatic mode is optimized to switch to the best compression scheme 
     automatically, but it can be manually controlled as a curiosity)
+    decoding:   qr decode hello.bmp
+    (reads BMP, XBM or text files, or stdin if no file is given, and
+    prints the contents of every code in the image)
+    damaging:   qr damage hello.bmp --noise 0.05 --circle 12,12,3
+    (damages a code on purpose, then checks if it can still be read)
+    grading:    qr grade scan.bmp
+    (grades the print quality of a code, from A to F, as per ISO 15415)
 
     settings:
         error correction level: -l (l|m|q|h)    (default: q)
         version (size): -v (1, 2, ..., 40)      (default: smallest possible)
+        largest version: --max-version (1..40)  (default: 40, and longer
+            messages are split over up to 16 codes, name-1 to name-16)
         masking pattern: -m (0, 1, ..., 7)      (default: lowest penalty score)
         name: -n (string)                       (default: \"out\")
         rescaling: -s (integer)                 (default: 512 pixels wide)
         XBM format output: --xbm                (default: BMP output)
+        GS1 data: --gs1                         (default: plain data)
+        other FNC1 data: --fnc1 (00..99|a..z)   (application indicator)
+        Micro QR code: --micro                  (versions 1 to 4 are M1 to M4,
+            masks are 0 to 3, and there's no level h)
+        rMQR code: --rmqr                       (levels m and h only)
+            height: --height (7|9|11|13|15|17)  (default: smallest area)
+            width: --width (27|43|59|77|99|139) (default: smallest area)
+        light on dark: --invert                 (default: dark on light)
+        mirror image: --mirror                  (default: not mirrored)
         read from stdin on empty input: --      (default: example message)
+        decoding details: decode --verbose      (default: contents only)
+        undescribed contents: decode --raw      (default: described)
+
+    damage (all in modules, \",light\" paints light instead of dark):
+        random flips: --noise (rate)
+        rectangle: --rect (x,y,width,height)
+        circle: --circle (x,y,radius)
+        line: --scratch (x1,y1,x2,y2,width)
+        missing corner: --corner (tl|tr|bl|br),(size)
+        random seed: --seed (integer)           (default: 0)
 
     note:
-        aliases --input, --ascii, --alphanum, --numeric, 
+        aliases --input, --ascii, --alphanum, --numeric, --kanji,
             --level, --version, --mask, --name, --scale are also available
         setting the rescaling to 0 renders the code at its original size,
             which is between 17 and 193 pixels wide. however, n:1 integer
             scaling (i.e., pixel accurate) is not implemented. also,
             rescaling to over 5000 pixels is ignored, because that's too big
-        the kanji compression mode is not supported; kanji will be rendered
-            as unicode instead, using (generally) the ASCII character mode
+        manual kanji mode (-kan) takes the characters of Shift-JIS (JIS X
+            0208): kanji, kana, full-width symbols, Greek and Cyrillic.
+            automatic mode uses it for those characters where it helps
         this program offers legacy XBM file output as an alternative to BMP.
             these files can be converted using GIMP, ImageMagick, etc";
 }
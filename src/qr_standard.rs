@@ -6,6 +6,22 @@ use crate::{image, rdsm::galois};
 pub mod badstream;
 /// High-level encoding of characters.
 pub mod bitstream;
+/// Character sets that can be selected with ECI markers.
+pub mod charset;
+/// Simulated damage, for testing error correction.
+pub mod damage;
+/// Reading data back out of QR codes.
+pub mod decode;
+/// Locating QR codes in images.
+pub mod detect;
+/// Micro QR codes, versions M1 to M4.
+pub mod micro;
+/// Recognizing common formats of QR code contents.
+pub mod payload;
+/// Grading the print quality of QR codes.
+pub mod quality;
+/// Rectangular Micro QR (rMQR) codes.
+pub mod rmqr;
 /// Lookup tables specific to the QR standard.
 pub mod tables;
 
@@ -67,6 +83,21 @@ impl image::Bitmap {
         }
     }
 
+    /// Read the error correction level and masking pattern of a QR code.
+    pub fn qr_format_info(&self) -> Option<FormatInfo> {
+        read_format_info(self, self.qr_version()?)
+    }
+
+    /// Read the version information of a QR code of version 7 or higher.
+    pub fn qr_version_info(&self) -> Option<VersionInfo> {
+        read_version_info(self, self.qr_version()?)
+    }
+
+    /// Decode the data in a QR code.
+    pub fn qr_decode(&self) -> Result<decode::Decoded, String> {
+        decode::read_qr(self)
+    }
+
     /// Create a blank QR code template.
     pub fn new_blank_qr(version: u32) -> Self {
         new_blank_qr_code(version)
@@ -89,19 +120,7 @@ fn qr_mask_xor(input: &mut image::Bitmap, mask: u8) {
             if let Some((x, y)) = input.debug_index_to_xy(vec_index, bit_index) {
                 if maybe_version.is_none() || coord_is_data(x, y, maybe_version.unwrap_or_default())
                 {
-                    mask_byte |= u8::from(
-                        0 == match mask {
-                            0 => (x + y) % 2,
-                            1 => y % 2,
-                            2 => x % 3,
-                            3 => (x + y) % 3,
-                            4 => (x / 3 + y / 2) % 2,
-                            5 => (x * y) % 2 + (x * y) % 3,
-                            6 => ((x * y) % 3 + x * y) % 2,
-                            7 => ((x * y) % 3 + x + y) % 2,
-                            _ => panic!(),
-                        },
-                    );
+                    mask_byte |= u8::from(mask_bit(mask, x, y));
                 }
             }
         }
@@ -109,6 +128,21 @@ fn qr_mask_xor(input: &mut image::Bitmap, mask: u8) {
     }
 }
 
+/// is a module flipped by a certain masking pattern?
+fn mask_bit(mask: u8, x: usize, y: usize) -> bool {
+    0 == match mask {
+        0 => (x + y) % 2,
+        1 => y % 2,
+        2 => x % 3,
+        3 => (x + y) % 3,
+        4 => (x / 3 + y / 2) % 2,
+        5 => (x * y) % 2 + (x * y) % 3,
+        6 => ((x * y) % 3 + x * y) % 2,
+        7 => ((x * y) % 3 + x + y) % 2,
+        _ => panic!(),
+    }
+}
+
 mod penalties {
     // Calculate the total penalty.
     pub fn total_penalty(input: &crate::image::Bitmap) -> u32 {
@@ -126,7 +160,7 @@ mod penalties {
                     pushy |=
                         usize::from(input.get_bit(x, y).expect("out of bounds")) << (ticker % size);
                     ticker += 1;
-                    if ticker % size == 0 {
+                    if ticker.is_multiple_of(size) {
                         bit_vector.push(pushy);
                         pushy = 0;
                     }
@@ -371,6 +405,58 @@ pub fn set_fcode(input: &mut image::Bitmap, version: u32, fcode: u16) {
     }
 }
 
+// in the style of set_fcode
+/// read both copies of the format code from a qr symbol, with the mask removed.
+pub fn get_fcode(input: &image::Bitmap, version: u32) -> (u16, u16) {
+    let mask = 0b0101_0100_0001_0010u16;
+
+    let (mut fcode1, mut fcode2) = (0, 0);
+    for bit in 0..=14 {
+        let ((x1, y1), (x2, y2)) = format_info_coords(version, bit).unwrap();
+        fcode1 |= u16::from(input.get_bit(x1, y1).unwrap_or_default()) << bit;
+        fcode2 |= u16::from(input.get_bit(x2, y2).unwrap_or_default()) << bit;
+    }
+    (fcode1 ^ mask, fcode2 ^ mask)
+}
+
+/// The error correction level and masking pattern of a QR symbol,
+/// as read from its format information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FormatInfo {
+    /// Error correction level, from 0 to 3 (L, M, Q, H).
+    pub level: u8,
+    /// Masking pattern, from 0 to 7.
+    pub mask: u8,
+    /// The number of bits that had to be corrected in the copy that was used.
+    pub bit_errors: u32,
+    /// Whether the two copies of the format code were identical.
+    pub copies_agree: bool,
+}
+
+/// Read and error-correct the format information of a QR symbol.
+///
+/// Both copies are read, and whichever one lies closest to a valid
+/// format code is trusted. Returns `None` if neither copy is within
+/// 3 bit errors of a valid code.
+pub fn read_format_info(input: &image::Bitmap, version: u32) -> Option<FormatInfo> {
+    let (fcode1, fcode2) = get_fcode(input, version);
+
+    let (fmt, bit_errors) = [fcode1, fcode2]
+        .into_iter()
+        .filter_map(galois::qr_correct_fcode)
+        .min_by_key(|&(_, distance)| distance)?;
+
+    // the level bits are ordered M - L - H - Q; this mapping is its own inverse
+    let level = [0b01, 0b00, 0b11, 0b10][(fmt >> 3) as usize];
+
+    Some(FormatInfo {
+        level,
+        mask: fmt & 0b111,
+        bit_errors,
+        copies_agree: fcode1 == fcode2,
+    })
+}
+
 // return the coordinates of a given byte/codeword in a qr symbol (quite inefficiently)
 // fn qr_data_coords(codeword: u32, bit: u8, version: u32) -> Option<(usize, usize)> {
 //     let size = version_to_size(version)?;
@@ -394,21 +480,40 @@ pub fn next_data_bit(x: usize, y: usize, version: u32) -> Option<(usize, usize)>
     }
 
     let max = version_to_max_index(version)?;
+    zigzag(x, y, (max, max), Some(6), |x, y| {
+        coord_is_data(x, y, version)
+    })
+}
+
+// the placement walk itself: two columns at a time, starting from
+// column `max_x`, up and down in turn. a vertical timing pattern in
+// column `skip` is skipped over, which shifts the columns left of it by one.
+// the walk ends when it runs out of columns on the left
+fn zigzag<F>(
+    x: usize,
+    y: usize,
+    (max_x, max_y): (usize, usize),
+    skip: Option<usize>,
+    is_data: F,
+) -> Option<(usize, usize)>
+where
+    F: Fn(usize, usize) -> bool,
+{
     let (mut x, mut y) = (x, y);
+    let shifted = |x: usize| skip.is_some_and(|skip| x < skip);
 
     // upper bound to avoid infinite loops
-    for _i in 0..(max + 1).pow(2) {
+    for _i in 0..(max_x + 1) * (max_y + 1) {
         // x coord is on the right-hand side of a codeword
-        // if x < 6, x needs to be odd; otherwise even
-        if (x % 2 == 0) ^ (x < 6) {
+        if (max_x - x).is_multiple_of(2) ^ shifted(x) {
             // ←
-            (x, y) = (x - 1, y);
+            (x, y) = (x.checked_sub(1)?, y);
         } else {
             // is the codeword being read from bottom to top (negative y direction)?
-            let up_codeword = (((max - x) / 2) % 2 == 0) ^ (x < 6);
+            let up_codeword = ((max_x - x) / 2).is_multiple_of(2) ^ shifted(x);
 
-            if (y == 0 && up_codeword) || (y == max && !up_codeword) {
-                (x, y) = (x - 1, y);
+            if (y == 0 && up_codeword) || (y == max_y && !up_codeword) {
+                (x, y) = (x.checked_sub(1)?, y);
             } else if up_codeword {
                 (x, y) = (x + 1, y - 1);
             } else {
@@ -416,16 +521,13 @@ pub fn next_data_bit(x: usize, y: usize, version: u32) -> Option<(usize, usize)>
             }
         }
 
-        if x == 6 {
+        if Some(x) == skip {
             x -= 1;
         }
 
-        if coord_is_data(x, y, version) {
+        if is_data(x, y) {
             break;
         }
-        if (x, y) == (0, max) {
-            return None;
-        }
     }
 
     Some((x, y))
@@ -589,3 +691,119 @@ pub fn set_vcode(input: &mut image::Bitmap, version: u32, vcode: u32) {
         input.set_bit(x2, y2, value);
     }
 }
+
+// in the style of get_fcode
+/// read both copies of the version code from a qr symbol:
+/// above the top right finder pattern, and left of the bottom left one.
+pub fn get_vcode(input: &image::Bitmap, version: u32) -> Option<(u32, u32)> {
+    let (mut vcode1, mut vcode2) = (0, 0);
+    for bit in 0..=17 {
+        let ((x1, y1), (x2, y2)) = version_info_coords(version, bit)?;
+        vcode1 |= u32::from(input.get_bit(x1, y1).unwrap_or_default()) << bit;
+        vcode2 |= u32::from(input.get_bit(x2, y2).unwrap_or_default()) << bit;
+    }
+    Some((vcode1, vcode2))
+}
+
+/// Find the valid version code closest to an 18-bit word.
+///
+/// Returns the version and the number of bits that had to be flipped
+/// to reach it. Version codes differ pairwise in at least 8 bits, so up
+/// to 3 bit errors are corrected; anything further away is rejected.
+pub fn qr_correct_vcode(vcode: u32) -> Option<(u32, u32)> {
+    (7..=40)
+        .map(|version| (version, (qr_generate_vcode(version) ^ vcode).count_ones()))
+        .min_by_key(|&(_, distance)| distance)
+        .filter(|&(_, distance)| distance <= 3)
+}
+
+/// The version of a QR symbol, as read from its version information.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VersionInfo {
+    /// The version, from 7 to 40.
+    pub version: u32,
+    /// The number of bits that had to be corrected in the copy that was used.
+    pub bit_errors: u32,
+    /// The copy that was used: 0 for the top right one, 1 for the bottom left one.
+    pub trusted_copy: usize,
+    /// Whether the two copies of the version code were identical.
+    pub copies_agree: bool,
+}
+
+/// Read and error-correct the version information of a QR symbol.
+///
+/// `version` is only used to locate the two copies, so it can be
+/// an estimate based on the symbol's size; the return value holds the
+/// version that's actually written in the symbol. Only versions 7 and up
+/// carry this information, so any lower version returns `None`.
+pub fn read_version_info(input: &image::Bitmap, version: u32) -> Option<VersionInfo> {
+    let (vcode1, vcode2) = get_vcode(input, version)?;
+
+    let (trusted_copy, (version, bit_errors)) = [vcode1, vcode2]
+        .into_iter()
+        .map(qr_correct_vcode)
+        .enumerate()
+        .filter_map(|(copy, corrected)| Some((copy, corrected?)))
+        .min_by_key(|&(_, (_, distance))| distance)?;
+
+    Some(VersionInfo {
+        version,
+        bit_errors,
+        trusted_copy,
+        copies_agree: vcode1 == vcode2,
+    })
+}
+
+// damage one copy of the format code beyond repair and the
+// other one within the limit, and check that it's still read
+#[test]
+fn format_info_round_trip() -> Result<(), String> {
+    use badstream::{make_qr, QRInput};
+
+    for level in 0..=3 {
+        for mask in 0..=7 {
+            let mut bitmap = make_qr(
+                QRInput::Auto("format".to_string()),
+                None,
+                Some(level),
+                Some(mask),
+            );
+            let version = bitmap.qr_version().unwrap();
+            for bit in [0, 4, 8, 9, 14] {
+                let ((x1, y1), _) = format_info_coords(version, bit).unwrap();
+                bitmap.set_bit(x1, y1, !bitmap.get_bit(x1, y1).unwrap());
+            }
+            for bit in [1, 7, 13] {
+                let (_, (x2, y2)) = format_info_coords(version, bit).unwrap();
+                bitmap.set_bit(x2, y2, !bitmap.get_bit(x2, y2).unwrap());
+            }
+
+            let info = bitmap.qr_format_info().ok_or("format info unreadable")?;
+            if (info.level, info.mask, info.bit_errors) != (level, mask, 3) || info.copies_agree {
+                return Err(format!("level {} mask {}: read {:?}", level, mask, info));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn version_info_round_trip() -> Result<(), String> {
+    for version in 7..=40 {
+        let mut bitmap = image::Bitmap::new_blank_qr(version);
+        // wreck the first copy, and flip 3 bits of the second
+        for bit in [0, 2, 5, 9, 12, 17] {
+            let ((x1, y1), (x2, y2)) = version_info_coords(version, bit).unwrap();
+            bitmap.set_bit(x1, y1, !bitmap.get_bit(x1, y1).unwrap());
+            if bit % 2 == 0 {
+                bitmap.set_bit(x2, y2, !bitmap.get_bit(x2, y2).unwrap());
+            }
+        }
+
+        let info = bitmap.qr_version_info().ok_or("version info unreadable")?;
+        if (info.version, info.bit_errors, info.trusted_copy) != (version, 3, 1) {
+            return Err(format!("version {}: read {:?}", version, info));
+        }
+    }
+    Ok(())
+}
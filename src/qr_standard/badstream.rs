@@ -1,5 +1,5 @@
 use super::{
-    bitstream::{self, search, Mode},
+    bitstream::{self, search, Fnc1, Mode},
     image, tables,
 };
 use crate::rdsm::{galois, poly};
@@ -18,7 +18,7 @@ pub fn badstream_to_polynomial(input: &Badstream) -> poly::Polynomial {
             pushbyte = 0;
         }
         pushbyte <<= 1;
-        pushbyte |= bit;
+        pushbyte |= u8::from(bit != 0);
     }
     output.push(pushbyte as galois::Element);
     output
@@ -34,7 +34,7 @@ pub fn pad_to(codeword_length: usize, stream: &mut Badstream) {
             stream.len(),
             8 * codeword_length
         )
-    } else if stream.len().div_ceil(8) > codeword_length - 1 && stream.len() % 8 != 0 {
+    } else if stream.len().div_ceil(8) > codeword_length - 1 && !stream.len().is_multiple_of(8) {
         panic!(
             "pad_to() error: {} bits away from bound; code will not scan",
             codeword_length * 8 - stream.len()
@@ -42,7 +42,7 @@ pub fn pad_to(codeword_length: usize, stream: &mut Badstream) {
     }
 
     // pad to next codeword boundary with zeros
-    if stream.len() % 8 != 0 {
+    if !stream.len().is_multiple_of(8) {
         stream.resize(stream.len().next_multiple_of(8), 0);
     }
 
@@ -143,7 +143,12 @@ pub fn split_to_blocks_and_encode(
 }
 
 pub fn full_block_encode(stream: &Badstream, version: u32, level: u8) -> Badstream {
-    let block_info = tables::get_block_info(version, level);
+    encode_blocks(stream, tables::get_block_info(version, level))
+}
+
+/// Pad a stream of data, split it into blocks, error correct the blocks
+/// and interleave them, for any layout of blocks.
+pub fn encode_blocks(stream: &Badstream, block_info: tables::VersionBlockInfo) -> Badstream {
     let (block_count, codewords, data_codewords, optional) = block_info;
     let ec_codewords = codewords - data_codewords;
     let (max_data_codewords, total_data_codewords) = match optional {
@@ -181,7 +186,8 @@ pub fn full_block_encode(stream: &Badstream, version: u32, level: u8) -> Badstre
 
     assert!(
         output.len() == 8 * total_data_codewords,
-        "full_block_encode(): version {} level {}: number of codewords should be {} but is {}\norig. data {:?}\noutput {:?}",version,level,
+        "encode_blocks(): {:?}: number of codewords should be {} but is {}\norig. data {:?}\noutput {:?}",
+        block_info,
         total_data_codewords,
         output.len() / 8,
         encoded_poly_vec,
@@ -203,8 +209,48 @@ pub fn full_block_encode(stream: &Badstream, version: u32, level: u8) -> Badstre
 pub enum QRInput {
     Auto(String),
     Manual(Vec<(Mode, String)>),
+    /// Either of the above, in an FNC1 mode, such as for GS1 data.
+    /// Fields are separated by [Fnc1::SEPARATOR].
+    Fnc1(Fnc1, Box<QRInput>),
+}
+
+impl QRInput {
+    // the fnc1 mode of the input, and the input without it
+    fn split_fnc1(self) -> (Option<Fnc1>, QRInput) {
+        match self {
+            QRInput::Fnc1(fnc1, input) => (Some(fnc1), input.split_fnc1().1),
+            input => (None, input),
+        }
+    }
+
+    /// The FNC1 mode of the input, and its segments. Automatic input is
+    /// optimized for the mode and count indicators of some kind of code.
+    pub fn into_segments_for(
+        self,
+        costs: search::SegmentCosts,
+    ) -> (Option<Fnc1>, Vec<(Mode, String)>) {
+        let (fnc1, input) = self.split_fnc1();
+        let segments = match input {
+            QRInput::Auto(str) => search::optimize_mode(
+                &str,
+                search::SegmentCosts {
+                    fnc1: fnc1.is_some(),
+                    ..costs
+                },
+            ),
+            QRInput::Manual(vec) => vec,
+            QRInput::Fnc1(..) => unreachable!(),
+        };
+        (fnc1, segments)
+    }
 }
 
+/// Make a message into a single QR code, of the chosen version or the
+/// smallest one that fits.
+///
+/// Panics if the message doesn't fit. [make_structured_append] takes the
+/// same input and splits long messages over several codes, returning a single
+/// code that's identical to this one when the message fits.
 pub fn make_qr(
     input: QRInput,
     version_choice: Option<u32>,
@@ -213,30 +259,16 @@ pub fn make_qr(
 ) -> image::Bitmap {
     let level = level_choice.unwrap_or(2);
 
-    // is utf8 (unicode) encoding necessary?
-    let utf8_encoding = match input {
-        // auto: check if string contains non-ascii chars
-        QRInput::Auto(ref str) => !str.is_ascii(),
-
-        // manual: check if any ASCII segment contains non-ascii chars
-        QRInput::Manual(ref vec) => vec.iter().any(|(m, s)| *m == Mode::ASCII && !s.is_ascii()),
-    };
-
+    let (fnc1, input) = input.split_fnc1();
     let input = match input {
-        QRInput::Auto(str) => find_best_mode_optimization(str, level),
+        QRInput::Auto(str) => find_best_mode_optimization(str, level, fnc1),
         QRInput::Manual(vec) => vec,
+        QRInput::Fnc1(..) => unreachable!(),
     };
+    let tokens = segments_to_tokens(input, fnc1);
 
-    let tokens = bitstream::make_token_stream(
-        input,
-        if utf8_encoding {
-            Some(tables::eci::UTF8)
-        } else {
-            None
-        },
-    );
-
-    let best_ver = bitstream::find_best_version(&tokens, level).expect("make_qr()");
+    let best_ver = bitstream::find_best_version(&tokens, level)
+        .unwrap_or_else(|e| panic!("make_qr(): {} (make_structured_append() can split it)", e));
 
     let version = match version_choice {
         Some(chosen_ver) => {
@@ -258,6 +290,33 @@ pub fn make_qr(
         None => best_ver,
     };
 
+    tokens_to_qr(tokens, version, level, mask_choice)
+}
+
+/// The tokens of a message, with an ECI marker if it needs one.
+pub fn segments_to_tokens(input: Vec<(Mode, String)>, fnc1: Option<Fnc1>) -> Vec<bitstream::Token> {
+    // is utf8 (unicode) encoding necessary?
+    // check if any ASCII segment contains non-ascii chars
+    // (kanji segments are always read as shift-jis)
+    let utf8_encoding = needs_utf8(&input);
+
+    bitstream::make_token_stream(
+        input,
+        if utf8_encoding {
+            Some(tables::eci::UTF8)
+        } else {
+            None
+        },
+        fnc1,
+    )
+}
+
+fn tokens_to_qr(
+    tokens: Vec<bitstream::Token>,
+    version: u32,
+    level: u8,
+    mask_choice: Option<u8>,
+) -> image::Bitmap {
     let shuffled_stream = full_block_encode(
         &bitstream::tokens_to_badstream(tokens, version),
         version,
@@ -279,6 +338,152 @@ pub fn make_qr(
     bitmap
 }
 
+/// Make a message into a Structured Append sequence: up to 16 codes, none
+/// bigger than `max_version` (40 if not given), that readers put back
+/// together into one message.
+///
+/// A message that fits in a single code comes out the same as from
+/// [make_qr]. A longer one is split into as few parts as `max_version`
+/// allows. The parts are filled one after another, at the smallest version
+/// that doesn't need any more of them, so the last part can be a lot shorter
+/// than the rest (and then gets a smaller version of its own, if it fits).
+pub fn make_structured_append(
+    input: QRInput,
+    max_version: Option<u32>,
+    level_choice: Option<u8>,
+    mask_choice: Option<u8>,
+) -> Result<Vec<image::Bitmap>, String> {
+    use bitstream::{find_best_version, Token};
+
+    let level = level_choice.unwrap_or(2);
+    let max_version = max_version.unwrap_or(40);
+    if super::bad_version(max_version) {
+        return Err(format!("invalid version {} chosen", max_version));
+    }
+
+    // the whole message in one code, exactly like make_qr() would make it
+    let (fnc1, input) = input.split_fnc1();
+    let whole = segments_to_tokens(
+        match input.clone() {
+            QRInput::Auto(str) => find_best_mode_optimization(str, level, fnc1),
+            QRInput::Manual(vec) => vec,
+            QRInput::Fnc1(..) => unreachable!(),
+        },
+        fnc1,
+    );
+    if let Ok(version) = find_best_version(&whole, level) {
+        if version <= max_version {
+            return Ok(vec![tokens_to_qr(whole, version, level, mask_choice)]);
+        }
+    }
+
+    // every character, with its mode if it was chosen manually
+    let characters: Vec<(Option<Mode>, char)> = match &input {
+        QRInput::Auto(str) => str.chars().map(|c| (None, c)).collect(),
+        QRInput::Manual(vec) => vec
+            .iter()
+            .flat_map(|(mode, str)| str.chars().map(|c| (Some(*mode), c)))
+            .collect(),
+        QRInput::Fnc1(..) => unreachable!(),
+    };
+
+    // the segments of a run of characters, optimized for the size class of
+    // a version if they're automatic
+    let segments = |range: std::ops::Range<usize>, version: u32| {
+        let part = &characters[range];
+        if part.first().is_some_and(|(mode, _)| mode.is_none()) {
+            let str: String = part.iter().map(|(_, c)| c).collect();
+            return optimize_mode(&str, tables::version_to_class(version), fnc1);
+        }
+        let mut output: Vec<(Mode, String)> = Vec::new();
+        for &(mode, c) in part {
+            let mode = mode.unwrap();
+            match output.last_mut() {
+                Some((last, str)) if *last == mode => str.push(c),
+                _ => output.push((mode, String::from(c))),
+            }
+        }
+        output
+    };
+
+    // split the message greedily into parts that fit in a version,
+    // or give up if that takes more than 16 of them
+    let split = |version: u32| {
+        let fits = |start: usize, end: usize| {
+            let mut tokens = segments_to_tokens(segments(start..end, version), fnc1);
+            tokens.insert(0, Token::StructuredAppend(0, 16, 0));
+            find_best_version(&tokens, level).is_ok_and(|best| best <= version)
+        };
+        let mut parts = Vec::new();
+        let mut start = 0;
+        while start < characters.len() {
+            if parts.len() == 16 || !fits(start, start + 1) {
+                return None;
+            }
+            // binary search for the longest part that fits
+            let (mut low, mut high) = (start + 1, characters.len());
+            while low < high {
+                let middle = (low + high).div_ceil(2);
+                if fits(start, middle) {
+                    low = middle;
+                } else {
+                    high = middle - 1;
+                }
+            }
+            parts.push(start..low);
+            start = low;
+        }
+        Some(parts)
+    };
+
+    // the smallest version that needs no more parts than the biggest one
+    let count = split(max_version)
+        .ok_or("the message doesn't fit in 16 codes")?
+        .len();
+    let (mut low, mut high) = (1, max_version);
+    while low < high {
+        let middle = (low + high) / 2;
+        if split(middle).is_some_and(|parts| parts.len() == count) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    let parts: Vec<_> = split(low)
+        .unwrap()
+        .into_iter()
+        .map(|range| segments(range, low))
+        .collect();
+
+    // the parity is taken over the bytes of the message as it's encoded,
+    // which for kanji means shift-jis
+    let mut parity = 0;
+    for (mode, str) in parts.iter().flatten() {
+        for c in str.chars() {
+            let mut buffer = [0; 4];
+            let bytes = match mode {
+                Mode::Kanji => &super::charset::shift_jis::from_char(c)
+                    .unwrap()
+                    .to_be_bytes(),
+                _ => c.encode_utf8(&mut buffer).as_bytes(),
+            };
+            parity = bytes.iter().fold(parity, |parity, byte| parity ^ byte);
+        }
+    }
+
+    let total = parts.len() as u8;
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, part)| {
+            let mut tokens = segments_to_tokens(part, fnc1);
+            tokens.insert(0, Token::StructuredAppend(index as u8, total, parity));
+            let version = find_best_version(&tokens, level).unwrap();
+            tokens_to_qr(tokens, version, level, mask_choice)
+        })
+        .collect())
+}
+
 fn apply_mask(bitmap: &mut image::Bitmap, version: u32, level: u8, mask: u8) {
     super::set_fcode(
         bitmap,
@@ -312,10 +517,7 @@ pub fn apply_best_mask(bitmap: &mut image::Bitmap, version: u32, level: u8) {
 /// what version QR code is chosen, which influences what
 /// mode switching is optimal), it's necessary to do this
 /// step before the optimal QR version can be decided on.
-fn find_best_mode_optimization(str: String, level: u8) -> Vec<(Mode, String)> {
-    // calculated once to not waste processing time
-    let maybe_eci_header = if !str.is_ascii() { 8 } else { 0 };
-
+fn find_best_mode_optimization(str: String, level: u8, fnc1: Option<Fnc1>) -> Vec<(Mode, String)> {
     // the limiting sizes for each code class, in codewords
     let class_limits = {
         let dcw = tables::DATA_CODEWORDS[level as usize];
@@ -325,14 +527,27 @@ fn find_best_mode_optimization(str: String, level: u8) -> Vec<(Mode, String)> {
     // check if the code fits in the first class, (version 1..)
     // then the second class (version 10..)
     for (class, limit) in class_limits.iter().enumerate() {
-        let marked_string = search::optimize_mode(&str, class as u8);
+        let marked_string = optimize_mode(&str, class as u8, fnc1);
+        let maybe_eci_header = if needs_utf8(&marked_string) { 8 } else { 0 };
+        let fnc1_header = match fnc1 {
+            Some(Fnc1::First) => 4,
+            Some(Fnc1::Second(_)) => 4 + 8,
+            None => 0,
+        };
 
         // calculate the total message size, in bits
         let cost = marked_string
             .iter()
-            .map(|(mode, string)| bit_cost(string.len(), class, *mode))
+            .map(|(mode, string)| match mode {
+                // in fnc1 mode, a literal % is written as %%
+                Mode::AlphaNum if fnc1.is_some() => {
+                    bit_cost(&string.replace('%', "%%"), class, *mode)
+                }
+                _ => bit_cost(string, class, *mode),
+            })
             .sum::<usize>()
-            + maybe_eci_header;
+            + maybe_eci_header
+            + fnc1_header;
 
         // if the message fits the limit with at least one codeword,
         // or exactly 0 bits, to spare, then return it
@@ -345,17 +560,35 @@ fn find_best_mode_optimization(str: String, level: u8) -> Vec<(Mode, String)> {
 
     // code must be third class (version 27..),
     // so no calculation is necessary
-    search::optimize_mode(&str, 2)
+    optimize_mode(&str, 2, fnc1)
+}
+
+// search::optimize_mode() for a size class of regular qr code
+fn optimize_mode(str: &str, class: u8, fnc1: Option<Fnc1>) -> Vec<(Mode, String)> {
+    let costs = search::SegmentCosts {
+        fnc1: fnc1.is_some(),
+        ..search::SegmentCosts::qr(class)
+    };
+    search::optimize_mode(&str.to_string(), costs)
+}
+
+fn needs_utf8(input: &[(Mode, String)]) -> bool {
+    input
+        .iter()
+        .any(|(m, s)| *m == Mode::ASCII && !s.is_ascii())
 }
 
 // verified accurate
-// returns the number of bits it takes to print `count` characters
+// returns the number of bits it takes to print a string
 // in a given mode and size class of qr code
-fn bit_cost(count: usize, class: usize, mode: Mode) -> usize {
+fn bit_cost(string: &str, class: usize, mode: Mode) -> usize {
     let cc_bits = tables::CC_INDICATOR_BITS[class];
+    let count = string.len();
     4 + match mode {
         Mode::Numeric => 4 + cc_bits[0] + ((10 * count + 1) as f32 / 3.0).round() as usize,
         Mode::AlphaNum => 4 + cc_bits[1] + 11 * (count / 2) + 6 * (count % 2),
         Mode::ASCII => 4 + cc_bits[2] + 8 * count,
+        // counted in characters, not bytes
+        Mode::Kanji => 4 + cc_bits[3] + 13 * string.chars().count(),
     }
 }
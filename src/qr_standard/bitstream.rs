@@ -1,4 +1,4 @@
-use super::{badstream, tables};
+use super::{badstream, charset::shift_jis, tables};
 
 /// The algorithm for size-optimal mode switching.
 pub mod search;
@@ -46,11 +46,44 @@ pub enum Mode {
     /// to the QR code as-is. Therefore, each ASCII [token character](Token::Character)
     /// is exactly one byte.
     ASCII,
+
+    /// The double-byte characters of Shift-JIS: kanji, kana, and
+    /// the Latin, Greek and Cyrillic letters and symbols of JIS X 0208.
+    ///
+    /// A kanji [token character](Token::Character) is one character,
+    /// which takes up 13 bits, compared to 24 bits for most of these
+    /// characters in UTF-8.
+    Kanji,
 }
 
 impl Mode {
     /// The three ASCII subsets, ordered by inclusion.
     pub const LIST: [Self; 3] = [Self::ASCII, Self::AlphaNum, Self::Numeric];
+
+    /// Every mode, with the ASCII subsets first.
+    pub const ALL: [Self; 4] = [Self::ASCII, Self::AlphaNum, Self::Numeric, Self::Kanji];
+}
+
+/// The two FNC1 modes, which mark a code's data as following
+/// the rules of an industry standard.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Fnc1 {
+    /// The data is formatted according to the GS1 General
+    /// Specifications, as a series of application identifiers.
+    First,
+
+    /// The data is formatted according to an industry standard
+    /// approved by AIM, identified by an application indicator:
+    /// either a two-digit number (0 to 99), or a letter's ASCII
+    /// code plus 100 (165 to 190 and 197 to 222).
+    Second(u8),
+}
+
+impl Fnc1 {
+    /// The GS (group separator) character that ends variable-length
+    /// fields. Alphanumeric segments write it as `%`, and a literal
+    /// `%` as `%%`.
+    pub const SEPARATOR: char = '\u{1d}';
 }
 
 // level 3
@@ -73,6 +106,16 @@ pub enum Token {
     /// from the QR code should be (re-)interpreted.
     EciChange(u32),
 
+    /// A Structured Append header, which links the code to
+    /// the others in a sequence.
+    ///
+    /// fields are the index of this code (from 0), the number
+    /// of codes, and the parity byte of the whole message.
+    StructuredAppend(u8, u8, u8),
+
+    /// An FNC1 mode indicator, which applies to the whole code.
+    Fnc1(Fnc1),
+
     /// the bit sequence `0000`
     Terminator,
 }
@@ -109,7 +152,15 @@ fn string_to_numeric(input: &str) -> Vec<Token> {
     output
 }
 
-fn string_to_alphanum(input: &str) -> Vec<Token> {
+fn string_to_alphanum(input: &str, fnc1: bool) -> Vec<Token> {
+    // in fnc1 mode, % stands for the separator character,
+    // so an actual % has to be doubled
+    let input = if fnc1 {
+        input.replace('%', "%%").replace(Fnc1::SEPARATOR, "%")
+    } else {
+        input.to_string()
+    };
+
     let mut output: Vec<Token> = vec![Token::ModeAndCount(Mode::AlphaNum, input.len() as u16)];
     for i in input
         .chars()
@@ -131,6 +182,26 @@ fn string_to_alphanum(input: &str) -> Vec<Token> {
     output
 }
 
+fn string_to_kanji(input: &str) -> Vec<Token> {
+    let mut output = vec![Token::ModeAndCount(
+        Mode::Kanji,
+        input.chars().count() as u16,
+    )];
+    for code in input
+        .chars()
+        .map(|x| shift_jis::from_char(x).expect("invalid kanji input!"))
+    {
+        // the two ranges of shift-jis codes are shifted down to start at 0,
+        // then the lead byte is multiplied by 0xC0 instead of 0x100
+        let offset = match code {
+            0x8140..=0x9FFC => code - 0x8140,
+            _ => code - 0xC140,
+        };
+        output.push(Token::Character(13, (offset >> 8) * 0xC0 + (offset & 0xFF)));
+    }
+    output
+}
+
 /// Convert a `Token` character into its equivalent bit sequence.
 fn push_token_to_badstream(stream: &mut badstream::Badstream, token: Token, version: u32) {
     use badstream::push_bits;
@@ -152,12 +223,27 @@ fn push_token_to_badstream(stream: &mut badstream::Badstream, token: Token, vers
             push_bits("0111", stream);
             push_bits(&string, stream);
         }
+        Token::Fnc1(Fnc1::First) => {
+            push_bits("0101", stream);
+        }
+        Token::Fnc1(Fnc1::Second(indicator)) => {
+            push_bits("1001", stream);
+            push_bits(&format!("{:08b}", indicator), stream);
+        }
+        Token::StructuredAppend(index, total, parity) => {
+            push_bits("0011", stream);
+            push_bits(
+                &format!("{:04b}{:04b}{:08b}", index, total - 1, parity),
+                stream,
+            );
+        }
         Token::ModeAndCount(mode, count) => {
             push_bits(
                 match mode {
                     Mode::Numeric => "0001",
                     Mode::AlphaNum => "0010",
                     Mode::ASCII => "0100",
+                    Mode::Kanji => "1000",
                 },
                 stream,
             );
@@ -178,17 +264,26 @@ fn push_token_to_badstream(stream: &mut badstream::Badstream, token: Token, vers
 }
 
 /// Stitch a vector of labeled strings into a vector of `Token` characters.
-pub fn make_token_stream(input: Vec<(Mode, String)>, eci: Option<u32>) -> Vec<Token> {
+pub fn make_token_stream(
+    input: Vec<(Mode, String)>,
+    eci: Option<u32>,
+    fnc1: Option<Fnc1>,
+) -> Vec<Token> {
     let mut stream: Vec<Token> = Vec::new();
 
     if let Some(char_set) = eci {
         stream.push(Token::EciChange(char_set));
     }
+    // the fnc1 indicator comes before any data
+    if let Some(fnc1) = fnc1 {
+        stream.push(Token::Fnc1(fnc1));
+    }
     for (mode, data) in input {
         stream.extend(match mode {
             Mode::Numeric => string_to_numeric(&data),
-            Mode::AlphaNum => string_to_alphanum(&data),
+            Mode::AlphaNum => string_to_alphanum(&data, fnc1.is_some()),
             Mode::ASCII => string_to_ascii(&data),
+            Mode::Kanji => string_to_kanji(&data),
         });
     }
     stream.push(Token::Terminator);
@@ -208,7 +303,7 @@ pub fn tokens_to_badstream(stream: Vec<Token>, version: u32) -> badstream::Badst
 
 /// A template to calculate the bit size of a series of tokens.
 ///
-/// ```
+/// ```ignore
 /// let (guaranteed_bits, [numeric_cc, alphanumeric_cc, ascii_cc, kanji_cc]): Overhead;
 /// ```
 /// Holds a count of the number of guaranteed bits in the encoded
@@ -221,9 +316,9 @@ pub fn tokens_to_badstream(stream: Vec<Token>, version: u32) -> badstream::Badst
 /// For example, an ASCII string with 1 character in it contains
 /// 4 bits for a mode marker, 8 bits for the character, 4 bits
 /// for the terminator, and a single ASCII character count indicator:
-/// ```
+/// ```ignore
 /// let data_vec = vec![(ASCII, "a".to_string())];
-/// let token_vec = make_token_stream(data_vec);
+/// let token_vec = make_token_stream(data_vec, None, None);
 /// let template = bit_overhead_template(&token_vec);
 ///
 /// assert_eq!(template, (16, [0, 0, 1, 0]));
@@ -250,8 +345,12 @@ fn bit_overhead_template(data: &Vec<Token>) -> Overhead {
                     Mode::Numeric => 0,
                     Mode::AlphaNum => 1,
                     Mode::ASCII => 2,
+                    Mode::Kanji => 3,
                 }] += 1;
             }
+            Token::StructuredAppend(..) => bit_sum += 4 + 16,
+            Token::Fnc1(Fnc1::First) => bit_sum += 4,
+            Token::Fnc1(Fnc1::Second(_)) => bit_sum += 4 + 8,
             Token::Character(length, _) => bit_sum += *length,
             Token::Terminator => bit_sum += 4,
         }
@@ -307,7 +406,8 @@ pub fn find_best_version(data: &Vec<Token>, level: u8) -> Result<u32, String> {
     ))
 }
 
-/// Returns the smallest mode subset of an ASCII character.
+/// Returns the smallest mode subset of an ASCII character,
+/// or [Mode::Kanji] for a character that's in Shift-JIS.
 fn char_status(x: char) -> Option<Mode> {
     Some(if x.is_ascii_digit() {
         // ascii, alphanumeric and numeric
@@ -318,6 +418,9 @@ fn char_status(x: char) -> Option<Mode> {
     } else if x.is_ascii() {
         // only ascii
         Mode::ASCII
+    } else if shift_jis::from_char(x).is_some() {
+        // kanji, or bytes
+        Mode::Kanji
     } else {
         return None;
     })
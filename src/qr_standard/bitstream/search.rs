@@ -7,10 +7,59 @@
 //
 // any message of length n has at most
 // 6n-6 edges (alternating between aln-asc).
-use super::{char_status, tables, Mode};
+use super::{char_status, tables, Fnc1, Mode};
 
 /// The cheapest known way to reach a character.
-type Cost = u32;
+type Cost = u64;
+
+/// The cost of a character in a mode the code doesn't have: high
+/// enough that it's only used when there's no other way.
+const UNAVAILABLE: Cost = 1 << 32;
+
+/// What it costs to start a segment in some kind of code, and how
+/// its characters are written.
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentCosts {
+    /// The size of a mode indicator, in bits.
+    pub mode_bits: usize,
+    /// The size of the character count indicator of the numeric,
+    /// alphanumeric, ASCII and kanji modes respectively, in bits.
+    /// 0 means the mode isn't available.
+    pub count_bits: [usize; 4],
+    /// Is the code in an FNC1 mode? Then the GS1 separator can be
+    /// written as an alphanumeric `%`, and a literal `%` takes two.
+    pub fnc1: bool,
+}
+
+impl SegmentCosts {
+    /// The costs in a regular QR code of a size class (0 to 2).
+    pub fn qr(class: u8) -> Self {
+        SegmentCosts {
+            mode_bits: 4,
+            count_bits: tables::CC_INDICATOR_BITS[class as usize],
+            fnc1: false,
+        }
+    }
+
+    /// The smallest subset a character is part of.
+    fn subset(&self, character: char) -> Mode {
+        if self.fnc1 && character == Fnc1::SEPARATOR {
+            Mode::AlphaNum
+        } else {
+            char_status(character).unwrap_or(Mode::ASCII)
+        }
+    }
+
+    /// The size of a mode's character count indicator.
+    fn count_bits(&self, mode: Mode) -> usize {
+        self.count_bits[match mode {
+            Mode::Numeric => 0,
+            Mode::AlphaNum => 1,
+            Mode::ASCII => 2,
+            Mode::Kanji => 3,
+        }]
+    }
+}
 
 /// A node in a graph, with extra information.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -41,19 +90,33 @@ impl PartialOrd for TaggedNode {
     }
 }
 
-/// The nodes associated with a character.
+/// The nodes associated with a character, and the smallest
+/// subset it's part of.
 ///
 /// Each character is given a node for every subset it is part of.
 #[derive(Clone, Copy)]
-struct CharNodes(Mode, [TaggedNode; 3]);
+struct CharNodes(char, Mode, [TaggedNode; 4]);
 
 impl CharNodes {
+    fn new(character: char, costs: &SegmentCosts) -> Self {
+        CharNodes(
+            character,
+            costs.subset(character),
+            [TaggedNode::default(); 4],
+        )
+    }
+
+    /// The smallest subset this character is part of.
+    fn mode(&self) -> Mode {
+        self.1
+    }
+
     /// Access a certain node for a given character.
     fn get(&self, category: Mode) -> Option<TaggedNode> {
         if !self.has(category) {
             None
         } else {
-            Some(self.1[category.index()])
+            Some(self.2[category.index()])
         }
     }
 
@@ -62,7 +125,7 @@ impl CharNodes {
         if !self.has(category) {
             None
         } else {
-            Some(&mut self.1[category.index()])
+            Some(&mut self.2[category.index()])
         }
     }
 
@@ -70,29 +133,32 @@ impl CharNodes {
     fn set_min(&mut self, category: Mode, value: TaggedNode) {
         if let Some(v) = self.get(category) {
             if value < v {
-                self.1[category.index()] = value;
+                self.2[category.index()] = value;
             }
         }
     }
 
     /// Does this character have a node of this type?
     fn has(&self, category: Mode) -> bool {
-        // this optimization doesn't hold for kanji...
-        category >= self.0
-        // category.index() <= self.max()
+        match self.mode() {
+            // kanji can also be written as (utf-8) bytes, but that's it
+            Mode::Kanji => matches!(category, Mode::Kanji | Mode::ASCII),
+            // every ascii subset is part of the next one up
+            mode => category != Mode::Kanji && category >= mode,
+        }
     }
 
     /// Propagate the lowest-cost route alternatives from
     /// the previous character.
     ///
     /// This function forms the backbone of the search algorithm.
-    fn score_from_predecessor(&mut self, from: &Self, class: u8) {
+    fn score_from_predecessor(&mut self, from: &Self, costs: &SegmentCosts) {
         // check each node we're going from
-        for from_mode in Mode::LIST.into_iter() {
+        for from_mode in Mode::ALL.into_iter() {
             // if that node exists,
             if let Some(TaggedNode(from_score, _)) = from.get(from_mode) {
                 // check each node we're moving towards
-                for to_mode in Mode::LIST.into_iter() {
+                for to_mode in Mode::ALL.into_iter() {
                     // and if THAT node exists,
                     if let Some(TaggedNode(..)) = self.get(to_mode) {
                         // are we moving between two nodes of the same type?
@@ -100,28 +166,24 @@ impl CharNodes {
 
                         // calculate the value of the node we're moving towards
                         let tentative_to_score =
-                            from_score + edge_weight(to_mode, same_subset, class);
+                            from_score + edge_weight(to_mode, self.0, same_subset, costs);
 
                         // if the score is lower than what's already there,
                         // i.e. we're on a more optimal path, replace it
                         self.set_min(to_mode, TaggedNode(tentative_to_score, Some(from_mode)));
-                    } else {
-                        break;
                     }
                 }
-            } else {
-                break;
             }
         }
     }
 
     /// The type of the node with the lowest cost.
     fn cheapest_mode(&self) -> Mode {
-        use Mode::{AlphaNum, Numeric, ASCII};
+        use Mode::{AlphaNum, Kanji, Numeric, ASCII};
 
         let mut cheapest_mode = ASCII;
         let mut lowest_cost = self.get(ASCII).unwrap();
-        for category in [AlphaNum, Numeric] {
+        for category in [AlphaNum, Numeric, Kanji] {
             if let Some(node) = self.get(category) {
                 if node < lowest_cost {
                     (cheapest_mode, lowest_cost) = (category, node);
@@ -142,45 +204,48 @@ type Graph = Vec<CharNodes>;
 /// 5.5 (11 / 2) and 3.33... (10 / 3) respectively.
 /// We multiply this by 6 to get an integer again,
 /// so numeric characters are awarded 20 points,
-/// alphanumerics 33, and ASCII characters 48.
+/// alphanumerics 33, kanji 78, and ASCII characters
+/// 48 per byte (non-ASCII characters take up to 4
+/// bytes in UTF-8).
 ///
 /// Switching to a new mode incurs an overhead (mode switch
 /// marker and character count indicator), which is only
 /// added if the `same_subset` flag is set to `false`.
-fn edge_weight(to_mode: Mode, same_subset: bool, class: u8) -> Cost {
+fn edge_weight(to_mode: Mode, character: char, same_subset: bool, costs: &SegmentCosts) -> Cost {
+    if costs.count_bits(to_mode) == 0 {
+        return UNAVAILABLE;
+    }
     (if !same_subset {
         // we multiply by 6 to get rid of decimals
-        // the 4 is the size of the mode indicator
-        6 * (4 + tables::cc_indicator_bit_size(class, to_mode)) as Cost
+        6 * (costs.mode_bits + costs.count_bits(to_mode)) as Cost
     } else {
         0
     }) + match to_mode {
         // 6 * 8
-        Mode::ASCII => 48,
+        Mode::ASCII => 48 * character.len_utf8() as Cost,
+        // in fnc1 mode, a literal % is written as %%
+        Mode::AlphaNum if costs.fnc1 && character == '%' => 66,
         // 6 * 11/2
         Mode::AlphaNum => 33,
         // 6 * 10/3
         Mode::Numeric => 20,
+        // 6 * 13
+        Mode::Kanji => 78,
     }
 }
 
 /// Create a graph of nodes, along with their respective costs and pointers.
-fn create_graph(mode_vec: &[Mode], class: u8) -> Graph {
-    let mut mode_iter = mode_vec.iter();
+fn create_graph(string: &str, costs: &SegmentCosts) -> Graph {
+    let mut char_iter = string.chars();
 
     // first character is a special case - the "same subset" parameter
     // is false for all modes
-    let mut current_nodes = CharNodes(
-        *mode_iter.next().expect("mode vector is empty"),
-        [TaggedNode::default(); 3],
-    );
-
-    for mode in Mode::LIST {
-        match current_nodes.get_mut(mode) {
-            Some(node) => {
-                *node = TaggedNode(edge_weight(mode, false, class), None);
-            }
-            None => break,
+    let first = char_iter.next().expect("string is empty");
+    let mut current_nodes = CharNodes::new(first, costs);
+
+    for mode in Mode::ALL {
+        if let Some(node) = current_nodes.get_mut(mode) {
+            *node = TaggedNode(edge_weight(mode, first, false, costs), None);
         }
     }
 
@@ -188,12 +253,9 @@ fn create_graph(mode_vec: &[Mode], class: u8) -> Graph {
 
     // remainder of graph
     let mut previous_nodes: CharNodes;
-    for &char_mode in mode_iter {
-        (previous_nodes, current_nodes) = (
-            current_nodes,
-            CharNodes(char_mode, [TaggedNode::default(); 3]),
-        );
-        current_nodes.score_from_predecessor(&previous_nodes, class);
+    for character in char_iter {
+        (previous_nodes, current_nodes) = (current_nodes, CharNodes::new(character, costs));
+        current_nodes.score_from_predecessor(&previous_nodes, costs);
         output.push(current_nodes);
     }
     output
@@ -227,21 +289,18 @@ fn optimal_path(graph: &Graph) -> Vec<Mode> {
     Vec::from(output)
 }
 
-/// Optimize
-pub fn optimize_mode(string: &String, class: u8) -> Vec<(Mode, String)> {
-    let char_to_mode = |x| char_status(x).unwrap_or(Mode::ASCII);
-
+/// Split a string into the segments that take up the fewest bits,
+/// given what segments cost in the code it's going into.
+pub fn optimize_mode(string: &String, costs: SegmentCosts) -> Vec<(Mode, String)> {
     if string.is_empty() {
         return vec![];
     }
-    if string.chars().count() == 1 {
-        let mode = char_to_mode(string.bytes().next().unwrap() as char);
-        return vec![(mode, string.to_string())];
+    let graph = create_graph(string, &costs);
+    if graph.len() == 1 {
+        return vec![(graph[0].cheapest_mode(), string.to_string())];
     }
 
-    let mode_vec = string.chars().map(char_to_mode).collect::<Vec<_>>();
-
-    let good_vec = optimal_path(&create_graph(&mode_vec, class));
+    let good_vec = optimal_path(&graph);
 
     let mut zip = good_vec.into_iter().zip(string.chars());
     let (mut current_mode, chr) = zip.next().unwrap();
@@ -269,6 +328,57 @@ impl Mode {
             Self::ASCII => 0,
             Self::AlphaNum => 1,
             Self::Numeric => 2,
+            Self::Kanji => 3,
+        }
+    }
+}
+
+// japanese text goes in kanji mode, and takes up a lot less room that way
+#[test]
+fn optimize_kanji() -> Result<(), String> {
+    use super::{find_best_version, make_token_stream};
+
+    let label = "商品番号 A-1234 賞味期限二〇二七年三月三十一日 冷蔵庫で保存してください";
+    let optimized = optimize_mode(&label.to_string(), SegmentCosts::qr(0));
+    let kanji: String = optimized
+        .iter()
+        .filter(|(mode, _)| *mode == Mode::Kanji)
+        .map(|(_, data)| data.as_str())
+        .collect();
+    if kanji.chars().count() < 30 {
+        return Err(format!("not enough kanji mode: {:?}", optimized));
+    }
+
+    let version = find_best_version(&make_token_stream(optimized, None, None), 2)?;
+    let bytes = find_best_version(
+        &make_token_stream(vec![(Mode::ASCII, label.to_string())], Some(26), None),
+        2,
+    )?;
+    if version + 2 > bytes {
+        return Err(format!("version {} with kanji, {} without", version, bytes));
+    }
+    Ok(())
+}
+
+// in fnc1 mode, the separator is alphanumeric and "%" costs twice as much
+#[test]
+fn optimize_fnc1() -> Result<(), String> {
+    let gs1 = SegmentCosts {
+        fnc1: true,
+        ..SegmentCosts::qr(0)
+    };
+    let separated = format!("AB{}CD", Fnc1::SEPARATOR);
+    let percents = "%".repeat(20);
+    for (string, costs, expected) in [
+        (&separated, gs1, vec![Mode::AlphaNum]),
+        (&separated, SegmentCosts::qr(0), vec![Mode::ASCII]),
+        (&percents, gs1, vec![Mode::ASCII]),
+        (&percents, SegmentCosts::qr(0), vec![Mode::AlphaNum]),
+    ] {
+        let optimized = optimize_mode(string, costs);
+        if optimized.iter().map(|(mode, _)| *mode).collect::<Vec<_>>() != expected {
+            return Err(format!("{:?} became {:?}", costs, optimized));
         }
     }
+    Ok(())
 }
@@ -0,0 +1,214 @@
+use super::{decode, image};
+use crate::random::Rng;
+
+/*
+damage is applied to a code with one pixel per module and no quiet zone,
+so all the sizes and positions here are in modules. shapes are filled by
+testing the center of every module, the same way a reader would sample it
+*/
+
+/// A corner of a code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A kind of damage that can be done to a code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Damage {
+    /// Flip every module with probability `rate`.
+    Noise { rate: f32 },
+    /// Paint over a rectangle, with its top left corner at `(x, y)`.
+    Rectangle {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        dark: bool,
+    },
+    /// Paint over a circle, centered at `(x, y)`.
+    Circle {
+        x: f32,
+        y: f32,
+        radius: f32,
+        dark: bool,
+    },
+    /// Paint a straight line between two points, `width` modules thick.
+    Scratch {
+        from: (f32, f32),
+        to: (f32, f32),
+        width: f32,
+        dark: bool,
+    },
+    /// Tear off a corner: a right triangle with legs `size` modules long,
+    /// left light.
+    Corner { corner: Corner, size: f32 },
+}
+
+/// The outcome of [simulate].
+#[derive(Clone)]
+pub struct DamageReport {
+    /// The code after the damage.
+    pub damaged: image::Bitmap,
+    /// The number of modules that ended up with the wrong color.
+    pub changed_modules: usize,
+    /// The result of decoding the damaged code. Its
+    /// [health](decode::Decoded::health) holds the errors in every block.
+    pub decoded: Result<decode::Decoded, String>,
+}
+
+/// Apply some damage to a code. `rng` is only used for [Damage::Noise].
+pub(crate) fn apply_damage(bitmap: &mut image::Bitmap, damage: Damage, rng: &mut Rng) {
+    let (width, height) = bitmap.dims();
+    for y in 0..height {
+        for x in 0..width {
+            // the center of the module
+            let (u, v) = (x as f32 + 0.5, y as f32 + 0.5);
+            let paint = match damage {
+                Damage::Noise { rate } => {
+                    if rng.chance(rate) {
+                        Some(!bitmap.get_bit(x, y).unwrap())
+                    } else {
+                        None
+                    }
+                }
+                Damage::Rectangle {
+                    x: left,
+                    y: top,
+                    width,
+                    height,
+                    dark,
+                } => {
+                    (u >= left && u < left + width && v >= top && v < top + height).then_some(dark)
+                }
+                Damage::Circle {
+                    x: cx,
+                    y: cy,
+                    radius,
+                    dark,
+                } => ((u - cx).hypot(v - cy) <= radius).then_some(dark),
+                Damage::Scratch {
+                    from,
+                    to,
+                    width,
+                    dark,
+                } => (distance_to_segment((u, v), from, to) <= width / 2.0).then_some(dark),
+                Damage::Corner { corner, size } => {
+                    // distances from the corner's two edges
+                    let (dx, dy) = match corner {
+                        Corner::TopLeft => (u, v),
+                        Corner::TopRight => (width as f32 - u, v),
+                        Corner::BottomLeft => (u, height as f32 - v),
+                        Corner::BottomRight => (width as f32 - u, height as f32 - v),
+                    };
+                    (dx + dy <= size).then_some(false)
+                }
+            };
+            if let Some(dark) = paint {
+                bitmap.set_bit(x, y, dark);
+            }
+        }
+    }
+}
+
+fn distance_to_segment(point: (f32, f32), from: (f32, f32), to: (f32, f32)) -> f32 {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((point.0 - from.0) * dx + (point.1 - from.1) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+    (point.0 - from.0 - t * dx).hypot(point.1 - from.1 - t * dy)
+}
+
+/// Damage a copy of a code, and try to decode it. The same `seed`
+/// always gives the same damage.
+pub fn simulate(bitmap: &image::Bitmap, damage: &[Damage], seed: u64) -> DamageReport {
+    let mut rng = Rng::new(seed);
+    let mut damaged = bitmap.clone();
+    for &d in damage {
+        apply_damage(&mut damaged, d, &mut rng);
+    }
+
+    let (width, height) = bitmap.dims();
+    let changed_modules = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| bitmap.get_bit(x, y) != damaged.get_bit(x, y))
+        .count();
+    let decoded = decode::read_qr(&damaged);
+
+    DamageReport {
+        damaged,
+        changed_modules,
+        decoded,
+    }
+}
+
+// light damage is corrected, and heavy damage isn't
+#[test]
+fn damage_levels() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    let bitmap = make_qr(QRInput::Auto("scuffed".to_string()), Some(5), Some(3), None);
+    let size = bitmap.dims().0 as f32;
+    for (damage, readable) in [
+        (Damage::Noise { rate: 0.02 }, true),
+        (Damage::Noise { rate: 0.3 }, false),
+        (
+            Damage::Circle {
+                x: size / 2.0,
+                y: size / 2.0,
+                radius: 4.0,
+                dark: true,
+            },
+            true,
+        ),
+        (
+            Damage::Scratch {
+                from: (0.0, size),
+                to: (size, 0.0),
+                width: 1.0,
+                dark: false,
+            },
+            true,
+        ),
+        (
+            Damage::Corner {
+                corner: Corner::BottomRight,
+                size: 8.0,
+            },
+            true,
+        ),
+        (
+            Damage::Rectangle {
+                x: 10.0,
+                y: 0.0,
+                width: size - 10.0,
+                height: size / 2.0,
+                dark: true,
+            },
+            false,
+        ),
+    ] {
+        let report = simulate(&bitmap, &[damage], 18);
+        let errors = match &report.decoded {
+            Ok(decoded) => decoded.health.blocks.iter().map(|b| b.errors).sum(),
+            Err(_) => 0,
+        };
+        let ok = report.decoded.as_ref().is_ok_and(|d| d.text() == "scuffed");
+        if report.changed_modules == 0 || ok != readable || (ok && errors == 0) {
+            return Err(format!(
+                "{:?}: {} modules changed, {} errors, {:?}",
+                damage,
+                report.changed_modules,
+                errors,
+                report.decoded.map(|d| d.text())
+            ));
+        }
+    }
+    Ok(())
+}
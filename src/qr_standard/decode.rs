@@ -0,0 +1,968 @@
+use super::{
+    badstream::{self, Badstream},
+    bitstream::{Fnc1, Mode},
+    charset, image, payload, tables,
+};
+use crate::rdsm::{galois, poly};
+
+/*
+decoding retraces the steps of make_qr() in reverse:
+1. read the format information, and remove the mask
+2. read the data modules in the same zigzag order they were written
+3. undo the block interleaving, and error-correct each block
+4. split the data bits back up into segments
+*/
+
+/// A run of data in a single mode, as read from a QR code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    /// The mode the data was encoded in.
+    pub mode: Mode,
+    /// The ECI marker in effect for this segment, if any.
+    pub eci: Option<u32>,
+    /// The FNC1 mode in effect for this segment, if any.
+    pub fnc1: Option<Fnc1>,
+    /// The decoded data. Numeric and alphanumeric segments
+    /// are given as the ASCII codes of their characters
+    /// (with `%` read as [Fnc1::SEPARATOR] in FNC1 mode),
+    /// and kanji segments as two Shift-JIS bytes per character.
+    pub data: Vec<u8>,
+}
+
+/// The contents of a decoded QR code.
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    pub version: u32,
+    /// Error correction level, from 0 to 3 (L, M, Q, H).
+    pub level: u8,
+    pub mask: u8,
+    pub segments: Vec<Segment>,
+    /// Whether the code was a mirror image (transposed).
+    pub mirrored: bool,
+    /// Whether the code was light on dark.
+    pub inverted: bool,
+    /// How much damage had to be corrected.
+    pub health: Health,
+    /// Where the code goes in a Structured Append sequence, if it's part of
+    /// one. See [join_structured_append].
+    pub structured_append: Option<StructuredAppend>,
+}
+
+/// The header of a code that holds one part of a longer message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StructuredAppend {
+    /// The position of this code in the sequence, starting from 0.
+    pub index: u8,
+    /// The number of codes in the sequence, up to 16.
+    pub total: u8,
+    /// The XOR of every byte of the whole message, the same in every part.
+    pub parity: u8,
+}
+
+/// How close a decoded QR code came to being unreadable.
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// The format information, with the number of bits that were wrong.
+    pub format: super::FormatInfo,
+    /// The number of wrong bits in each copy of the format information,
+    /// the one around the top left finder pattern first.
+    pub format_bit_errors: [u32; 2],
+    /// The version information, for versions 7 and up.
+    pub version: Option<super::VersionInfo>,
+    /// The number of wrong bits in each copy of the version information,
+    /// the one by the top right finder pattern first.
+    pub version_bit_errors: Option<[u32; 2]>,
+    /// The error correction of every block, in the order of
+    /// [get_block_info](tables::get_block_info).
+    pub blocks: Vec<BlockHealth>,
+}
+
+/// The errors that were corrected in one block of codewords.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockHealth {
+    pub data_codewords: usize,
+    pub ec_codewords: usize,
+    /// Codewords that were wrong, at unknown positions.
+    pub errors: usize,
+    /// Codewords that were marked as unreadable in advance.
+    pub erasures: usize,
+}
+
+impl BlockHealth {
+    /// How much more damage the block could have taken, counted in erasures.
+    /// An error costs two erasures, so `margin() / 2` more errors would
+    /// still have been corrected. 0 if the counts are already past what
+    /// the block can correct.
+    pub fn margin(&self) -> usize {
+        self.ec_codewords
+            .saturating_sub(2 * self.errors + self.erasures)
+    }
+}
+
+impl Health {
+    /// The smallest [margin](BlockHealth::margin) of all the blocks.
+    pub fn margin(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(BlockHealth::margin)
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+impl Segment {
+    /// The data in the segment, converted to text from the character set
+    /// given by its ECI marker. See [charset::decode].
+    ///
+    /// Without an ECI marker, the data is read as UTF-8 if it's valid UTF-8,
+    /// and as ISO 8859-1 (the standard's default) otherwise.
+    ///
+    /// Numeric, alphanumeric and kanji segments don't depend on the ECI.
+    ///
+    /// Returns `None` if the ECI isn't a supported character set, in which
+    /// case the raw bytes are in [Segment::data].
+    pub fn text(&self) -> Option<String> {
+        match self.mode {
+            Mode::ASCII => decode_bytes(self.eci, &self.data),
+            Mode::Kanji => Some(charset::shift_jis::decode(&self.data)),
+            _ => Some(String::from_utf8_lossy(&self.data).into_owned()),
+        }
+    }
+}
+
+fn decode_bytes(eci: Option<u32>, data: &[u8]) -> Option<String> {
+    match eci {
+        Some(designator) => charset::decode(designator, data),
+        None => Some(match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => charset::decode(tables::eci::LATIN_1, data)?,
+        }),
+    }
+}
+
+impl Decoded {
+    /// The FNC1 mode of the code, if it has one.
+    pub fn fnc1(&self) -> Option<Fnc1> {
+        self.segments.iter().find_map(|s| s.fnc1)
+    }
+
+    /// All the data in the code, concatenated into one byte string.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .flat_map(|s| s.data.iter().copied())
+            .collect()
+    }
+
+    /// The data in the code as text, with every segment converted from the
+    /// character set given by its ECI marker, as in [Segment::text].
+    ///
+    /// Data in unsupported character sets is read as UTF-8 instead, and any
+    /// invalid UTF-8 sequences are replaced with `U+FFFD`.
+    pub fn text(&self) -> String {
+        let mut output = String::new();
+
+        // a character can be split between two byte segments, so every run
+        // of byte segments under the same ECI is converted in one go
+        let mut start = 0;
+        while start < self.segments.len() {
+            let first = &self.segments[start];
+            if first.mode != Mode::ASCII {
+                output.push_str(&first.text().unwrap());
+                start += 1;
+                continue;
+            }
+            let end = self.segments[start..]
+                .iter()
+                .position(|s| s.mode != Mode::ASCII || s.eci != first.eci)
+                .map_or(self.segments.len(), |length| start + length);
+            let data: Vec<u8> = self.segments[start..end]
+                .iter()
+                .flat_map(|s| s.data.iter().copied())
+                .collect();
+            match decode_bytes(first.eci, &data) {
+                Some(text) => output.push_str(&text),
+                None => output.push_str(&String::from_utf8_lossy(&data)),
+            }
+            start = end;
+        }
+        output
+    }
+
+    /// The [text](Decoded::text) in the code, recognized as a link, contact
+    /// card, wi-fi login etc. where possible.
+    pub fn payload(&self) -> payload::Payload {
+        payload::parse(&self.text())
+    }
+}
+
+/// Decode a QR code from a bitmap with one pixel per module and no quiet zone.
+///
+/// Mirror-image and light-on-dark codes are read as well.
+pub fn read_qr(bitmap: &image::Bitmap) -> Result<Decoded, String> {
+    let (width, height) = bitmap.dims();
+    read_qr_with_erasures(bitmap, &image::Bitmap::new(width, height))
+}
+
+/// [read_qr], with some modules known to be unreadable: they're set in
+/// `erasures`, which is the same size as `bitmap`. Every codeword with
+/// an unreadable module in it is corrected as an erasure.
+pub fn read_qr_with_erasures(
+    bitmap: &image::Bitmap,
+    erasures: &image::Bitmap,
+) -> Result<Decoded, String> {
+    if bitmap.dims() != erasures.dims() {
+        return Err("the erasures are a different size from the code".to_string());
+    }
+    let mut first_error = None;
+    for (mirrored, inverted) in [(false, false), (true, false), (false, true), (true, true)] {
+        let (mut oriented, mut oriented_erasures) = (bitmap.clone(), erasures.clone());
+        if mirrored {
+            oriented = oriented.transpose();
+            oriented_erasures = oriented_erasures.transpose();
+        }
+        if inverted {
+            oriented = oriented.invert();
+        }
+        match read_qr_as_is(&oriented, &oriented_erasures) {
+            Ok(decoded) => {
+                return Ok(Decoded {
+                    mirrored,
+                    inverted,
+                    ..decoded
+                })
+            }
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    Err(first_error.unwrap())
+}
+
+// read_qr(), for a code that's the right way around
+fn read_qr_as_is(bitmap: &image::Bitmap, erasures: &image::Bitmap) -> Result<Decoded, String> {
+    let version = bitmap
+        .qr_version()
+        .ok_or("bitmap dimensions don't match any QR code version")?;
+
+    let version_info = bitmap.qr_version_info();
+    if let Some(info) = version_info {
+        if info.version != version {
+            return Err(format!(
+                "version information says {} but the symbol is the size of version {}",
+                info.version, version
+            ));
+        }
+    }
+
+    let format = bitmap
+        .qr_format_info()
+        .ok_or("format information is unreadable")?;
+
+    // masking is its own inverse
+    let mut unmasked = bitmap.clone();
+    unmasked.qr_mask_xor(format.mask);
+
+    let stream = read_badstream_from_bitmap(&unmasked, version);
+    let erased = read_badstream_from_bitmap(erasures, version);
+    let (data, blocks) = full_block_decode_with_erasures(&stream, &erased, version, format.level)?;
+    let segments = badstream_to_segments(&data, version)?;
+
+    Ok(Decoded {
+        structured_append: read_structured_append(&data),
+        version,
+        level: format.level,
+        mask: format.mask,
+        segments,
+        mirrored: false,
+        inverted: false,
+        health: Health {
+            format,
+            format_bit_errors: format_bit_errors(bitmap, version),
+            version: version_info,
+            version_bit_errors: version_bit_errors(bitmap, version),
+            blocks,
+        },
+    })
+}
+
+// the distance from both copies to the format code that was trusted
+fn format_bit_errors(bitmap: &image::Bitmap, version: u32) -> [u32; 2] {
+    let copies = super::get_fcode(bitmap, version);
+    let trusted = [copies.0, copies.1]
+        .into_iter()
+        .filter_map(galois::qr_correct_fcode)
+        .min_by_key(|&(_, distance)| distance)
+        .and_then(|(fmt, _)| galois::qr_generate_fcode(fmt))
+        .unwrap_or_default();
+    [copies.0, copies.1].map(|copy| (copy ^ trusted).count_ones())
+}
+
+// the same for the version code
+fn version_bit_errors(bitmap: &image::Bitmap, version: u32) -> Option<[u32; 2]> {
+    let copies = super::get_vcode(bitmap, version)?;
+    let trusted = super::qr_generate_vcode(bitmap.qr_version_info()?.version);
+    Some([copies.0, copies.1].map(|copy| (copy ^ trusted).count_ones()))
+}
+
+/// The inverse of [badstream::write_badstream_to_bitmap]: read every data module.
+pub fn read_badstream_from_bitmap(bitmap: &image::Bitmap, version: u32) -> Badstream {
+    let max = bitmap.dims().0 - 1;
+    let (mut x, mut y) = (max, max);
+    let mut output = Badstream::new();
+    loop {
+        output.push(u8::from(bitmap.get_bit(x, y).unwrap_or_default()));
+        match super::next_data_bit(x, y, version) {
+            Some((x2, y2)) => (x, y) = (x2, y2),
+            None => break,
+        }
+    }
+    output
+}
+
+/// The inverse of [badstream::full_block_encode].
+///
+/// Splits the interleaved codewords back up into blocks, corrects
+/// each one, and returns the data codewords as a bit stream.
+pub fn full_block_decode(stream: &Badstream, version: u32, level: u8) -> Result<Badstream, String> {
+    let no_erasures = vec![0; stream.len()];
+    full_block_decode_with_erasures(stream, &no_erasures, version, level).map(|(data, _)| data)
+}
+
+/// [full_block_decode], with the bits of unreadable codewords set in `erased`,
+/// a stream of the same length. Also returns how many errors and erasures
+/// were corrected in each block.
+pub fn full_block_decode_with_erasures(
+    stream: &Badstream,
+    erased: &Badstream,
+    version: u32,
+    level: u8,
+) -> Result<(Badstream, Vec<BlockHealth>), String> {
+    let (block_count, codewords, data_codewords, optional) = tables::get_block_info(version, level);
+    let ec_codewords = codewords - data_codewords;
+    let (block_count_2, codewords_2, data_codewords_2) = optional.unwrap_or((0, 0, 0));
+    let total_codewords = block_count * codewords + block_count_2 * codewords_2;
+
+    // any bits left over after the last codeword are remainder bits
+    if stream.len() < 8 * total_codewords || erased.len() < 8 * total_codewords {
+        return Err(format!(
+            "symbol holds {} bits but version {} needs {}",
+            stream.len(),
+            version,
+            8 * total_codewords
+        ));
+    }
+    let mut codeword_iter =
+        badstream::badstream_to_polynomial(&stream[..8 * total_codewords].to_vec())
+            .into_iter()
+            .zip(
+                erased
+                    .chunks(8)
+                    .map(|bits| bits.iter().any(|&bit| bit != 0)),
+            );
+
+    // the data length of every block, in order
+    let data_lengths: Vec<usize> = std::iter::repeat_n(data_codewords, block_count)
+        .chain(std::iter::repeat_n(data_codewords_2, block_count_2))
+        .collect();
+    let max_data_codewords = *data_lengths.last().unwrap();
+
+    // (codewords, indices of the erased ones)
+    let mut blocks: Vec<(poly::Polynomial, Vec<usize>)> =
+        vec![(Vec::new(), Vec::new()); data_lengths.len()];
+    let mut push = |(block, erasures): &mut (poly::Polynomial, Vec<usize>)| {
+        let (codeword, erased) = codeword_iter.next().unwrap();
+        if erased {
+            erasures.push(block.len());
+        }
+        block.push(codeword);
+    };
+
+    // data codewords are interleaved first, with the short blocks
+    // dropping out on the last round
+    for i in 0..max_data_codewords {
+        for (block, &length) in blocks.iter_mut().zip(&data_lengths) {
+            if i < length {
+                push(block);
+            }
+        }
+    }
+    // then the error-correcting codewords
+    for _ in 0..ec_codewords {
+        for block in blocks.iter_mut() {
+            push(block);
+        }
+    }
+
+    let mut output = Badstream::new();
+    let mut health = Vec::with_capacity(blocks.len());
+    for (i, (block, erasures)) in blocks.iter().enumerate() {
+        let (data, corrected) =
+            poly::decode_message_with_erasures(block, ec_codewords as u32, erasures)
+                .map_err(|e| format!("block {}: {}", i, e))?;
+        health.push(BlockHealth {
+            data_codewords: data.len(),
+            ec_codewords,
+            errors: corrected.iter().filter(|c| !erasures.contains(c)).count(),
+            erasures: erasures.len(),
+        });
+        for codeword in data {
+            badstream::push_byte(codeword as galois::Element, &mut output);
+        }
+    }
+    Ok((output, health))
+}
+
+/// Reads bits off the front of a [Badstream].
+struct BitReader<'a> {
+    stream: &'a Badstream,
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.stream.len() - self.position
+    }
+
+    /// Read `width` bits as an unsigned integer, highest bit first.
+    fn read(&mut self, width: usize) -> Result<u32, String> {
+        if width > self.remaining() {
+            return Err("data ends in the middle of a segment".to_string());
+        }
+        let mut output = 0;
+        for &bit in &self.stream[self.position..self.position + width] {
+            output = (output << 1) | u32::from(bit != 0);
+        }
+        self.position += width;
+        Ok(output)
+    }
+}
+
+/// The inverse of [bitstream::tokens_to_badstream](super::bitstream::tokens_to_badstream):
+/// split a stream of data bits up into segments.
+pub fn badstream_to_segments(stream: &Badstream, version: u32) -> Result<Vec<Segment>, String> {
+    let class = tables::version_to_class(version);
+    let mut reader = BitReader {
+        stream,
+        position: 0,
+    };
+    let mut eci = None;
+    let mut fnc1 = None;
+    let mut output = Vec::new();
+
+    // a stream that fills the symbol exactly may leave out the terminator
+    while reader.remaining() >= 4 {
+        let mode = match reader.read(4)? {
+            0b0000 => break,
+            0b0001 => Mode::Numeric,
+            0b0010 => Mode::AlphaNum,
+            0b0100 => Mode::ASCII,
+            0b1000 => Mode::Kanji,
+            0b0111 => {
+                eci = Some(read_eci_designator(&mut reader)?);
+                continue;
+            }
+            0b0101 => {
+                fnc1 = Some(Fnc1::First);
+                continue;
+            }
+            0b1001 => {
+                fnc1 = Some(Fnc1::Second(reader.read(8)? as u8));
+                continue;
+            }
+            // see read_structured_append
+            0b0011 => {
+                reader.read(16)?;
+                continue;
+            }
+            other => return Err(format!("unsupported mode indicator {:04b}", other)),
+        };
+
+        let count = reader.read(tables::cc_indicator_bit_size(class, mode))? as usize;
+        let data = match mode {
+            Mode::Numeric => read_numeric(&mut reader, count)?,
+            Mode::AlphaNum if fnc1.is_some() => unescape_fnc1(&read_alphanum(&mut reader, count)?),
+            Mode::AlphaNum => read_alphanum(&mut reader, count)?,
+            Mode::ASCII => (0..count)
+                .map(|_| reader.read(8).map(|b| b as u8))
+                .collect::<Result<_, _>>()?,
+            Mode::Kanji => read_kanji(&mut reader, count)?,
+        };
+        output.push(Segment {
+            mode,
+            eci,
+            fnc1,
+            data,
+        });
+    }
+    Ok(output)
+}
+
+// in fnc1 mode, alphanumeric "%" means the separator and "%%" means "%"
+fn unescape_fnc1(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut iter = data.iter().copied().peekable();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' && iter.next_if_eq(&b'%').is_none() {
+            output.push(Fnc1::SEPARATOR as u8);
+        } else {
+            output.push(byte);
+        }
+    }
+    output
+}
+
+/// The Structured Append header at the start of a stream of data bits, if
+/// there is one.
+pub fn read_structured_append(stream: &Badstream) -> Option<StructuredAppend> {
+    let mut reader = BitReader {
+        stream,
+        position: 0,
+    };
+    if reader.read(4).ok()? != 0b0011 {
+        return None;
+    }
+    let header = reader.read(16).ok()?;
+    Some(StructuredAppend {
+        index: (header >> 12) as u8,
+        total: (header >> 8 & 0xF) as u8 + 1,
+        parity: header as u8,
+    })
+}
+
+/// Put the text of a Structured Append sequence back together.
+///
+/// The parts can be given in any order, but they all have to be there,
+/// and their parity has to match the message.
+pub fn join_structured_append(parts: &[Decoded]) -> Result<String, String> {
+    let mut sorted: Vec<(StructuredAppend, &Decoded)> = parts
+        .iter()
+        .map(|part| {
+            part.structured_append
+                .map(|header| (header, part))
+                .ok_or("a code isn't part of a Structured Append sequence")
+        })
+        .collect::<Result<_, _>>()?;
+    sorted.sort_by_key(|(header, _)| header.index);
+
+    let (first, _) = *sorted.first().ok_or("no codes to join")?;
+    for (i, (header, _)) in sorted.iter().enumerate() {
+        if (header.total, header.parity) != (first.total, first.parity) {
+            return Err("the codes are from different sequences".to_string());
+        }
+        if header.index as usize != i {
+            return Err(format!("part {} of {} is missing", i + 1, first.total));
+        }
+    }
+    if sorted.len() != first.total as usize {
+        return Err(format!(
+            "part {} of {} is missing",
+            sorted.len() + 1,
+            first.total
+        ));
+    }
+
+    let parity = sorted
+        .iter()
+        .flat_map(|(_, part)| part.bytes())
+        .fold(0, |parity, byte| parity ^ byte);
+    if parity != first.parity {
+        return Err(format!(
+            "parity is {:02X}, but the codes say {:02X}",
+            parity, first.parity
+        ));
+    }
+    Ok(sorted.iter().map(|(_, part)| part.text()).collect())
+}
+
+// the inverse of the EciChange arm of push_token_to_badstream
+fn read_eci_designator(reader: &mut BitReader) -> Result<u32, String> {
+    let first = reader.read(8)?;
+    Ok(if first & 0x80 == 0 {
+        // 0bbb bbbb
+        first
+    } else if first & 0xC0 == 0x80 {
+        // 10bb bbbb  bbbb bbbb
+        ((first & 0x3F) << 8) | reader.read(8)?
+    } else if first & 0xE0 == 0xC0 {
+        // 110b bbbb  bbbb bbbb  bbbb bbbb
+        ((first & 0x1F) << 16) | reader.read(16)?
+    } else {
+        return Err(format!("invalid ECI designator {:08b}", first));
+    })
+}
+
+fn read_numeric(reader: &mut BitReader, count: usize) -> Result<Vec<u8>, String> {
+    let mut output = Vec::with_capacity(count);
+    let mut left = count;
+    while left > 0 {
+        // 3 digits in 10 bits, 2 in 7, 1 in 4
+        let digits = left.min(3);
+        let width = [0, 4, 7, 10][digits];
+        let value = reader.read(width)?;
+        if value >= 10u32.pow(digits as u32) {
+            return Err(format!("invalid numeric value {}", value));
+        }
+        let string = format!("{:0width$}", value, width = digits);
+        output.extend(string.bytes());
+        left -= digits;
+    }
+    Ok(output)
+}
+
+fn read_alphanum(reader: &mut BitReader, count: usize) -> Result<Vec<u8>, String> {
+    let set = tables::ALPHANUM_SET.as_bytes();
+    let mut output = Vec::with_capacity(count);
+    for _ in 0..count / 2 {
+        let value = reader.read(11)? as usize;
+        if value >= 45 * 45 {
+            return Err(format!("invalid alphanumeric value {}", value));
+        }
+        output.push(set[value / 45]);
+        output.push(set[value % 45]);
+    }
+    if count % 2 == 1 {
+        let value = reader.read(6)? as usize;
+        output.push(*set.get(value).ok_or("invalid alphanumeric value")?);
+    }
+    Ok(output)
+}
+
+// the inverse of bitstream::string_to_kanji
+fn read_kanji(reader: &mut BitReader, count: usize) -> Result<Vec<u8>, String> {
+    let mut output = Vec::with_capacity(2 * count);
+    for _ in 0..count {
+        let value = reader.read(13)?;
+        let offset = (value / 0xC0) << 8 | (value % 0xC0);
+        let code = match offset + 0x8140 {
+            code @ 0x8140..=0x9FFC => code,
+            _ => offset + 0xC140,
+        };
+        output.extend((code as u16).to_be_bytes());
+    }
+    Ok(output)
+}
+
+#[test]
+fn decode_make_qr() -> Result<(), String> {
+    use badstream::{make_qr, QRInput};
+
+    for (text, version) in [
+        ("Hello, world! 123", None),
+        ("HTTPS://EXAMPLE.COM/0123456789", Some(9)),
+        ("\u{1f499} \u{2013} esmeralda", Some(12)),
+        ("0118 999 881 999 119 725 3", None),
+        (
+            "\u{6f22}\u{5b57}\u{3068}\u{304b}\u{306a} (kanji) \u{1f499}",
+            None,
+        ),
+        ("", Some(30)),
+    ] {
+        for level in 0..=3 {
+            let bitmap = make_qr(QRInput::Auto(text.to_string()), version, Some(level), None);
+            let decoded = read_qr(&bitmap)?;
+            if decoded.text() != text || decoded.level != level {
+                return Err(format!("read {:?} from {:?}", decoded, text));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn decode_reversed() -> Result<(), String> {
+    use badstream::{make_qr, QRInput};
+
+    let bitmap = make_qr(QRInput::Auto("through glass".to_string()), None, None, None);
+    for (mirrored, inverted) in [(false, false), (true, false), (false, true), (true, true)] {
+        let mut oriented = bitmap.clone();
+        if mirrored {
+            oriented = oriented.transpose();
+        }
+        if inverted {
+            oriented = oriented.invert();
+        }
+        let decoded = read_qr(&oriented)?;
+        if decoded.text() != "through glass"
+            || (decoded.mirrored, decoded.inverted) != (mirrored, inverted)
+        {
+            return Err(format!("read {:?}", decoded));
+        }
+    }
+    Ok(())
+}
+
+// data from other encoders, in other character sets
+#[test]
+fn decode_eci_segments() -> Result<(), String> {
+    use super::bitstream::{tokens_to_badstream, Token};
+
+    let bytes = |data: &[u8]| {
+        let mut tokens = vec![Token::ModeAndCount(Mode::ASCII, data.len() as u16)];
+        tokens.extend(data.iter().map(|&b| Token::Character(8, b as u16)));
+        tokens
+    };
+    for (tokens, text) in [
+        (
+            [
+                vec![Token::EciChange(tables::eci::WINDOWS_1252)],
+                bytes(b"\x93caf\xE9\x94"),
+            ]
+            .concat(),
+            "\u{201c}café\u{201d}",
+        ),
+        (
+            // numeric data isn't affected by the ECI
+            [
+                vec![Token::EciChange(tables::eci::UTF16_BE)],
+                bytes(b"\x04\x1f"),
+                vec![
+                    Token::ModeAndCount(Mode::Numeric, 1),
+                    Token::Character(4, 7),
+                ],
+                bytes(b"\x00!"),
+            ]
+            .concat(),
+            "\u{41f}7!",
+        ),
+        // no ECI, not UTF-8
+        (bytes(b"na\xEFve"), "naïve"),
+    ] {
+        let stream = tokens_to_badstream([tokens, vec![Token::Terminator]].concat(), 1);
+        let decoded = Decoded {
+            version: 1,
+            level: 0,
+            mask: 0,
+            segments: badstream_to_segments(&stream, 1)?,
+            structured_append: None,
+            mirrored: false,
+            inverted: false,
+            health: Health {
+                format: super::FormatInfo {
+                    level: 0,
+                    mask: 0,
+                    bit_errors: 0,
+                    copies_agree: true,
+                },
+                format_bit_errors: [0, 0],
+                version: None,
+                version_bit_errors: None,
+                blocks: Vec::new(),
+            },
+        };
+        if decoded.text() != text {
+            return Err(format!("read {:?} instead of {:?}", decoded.text(), text));
+        }
+    }
+    Ok(())
+}
+
+// damage a few codewords and format bits, and see it in the report
+#[test]
+fn decode_health() -> Result<(), String> {
+    use badstream::{make_qr, QRInput};
+
+    let mut bitmap = make_qr(
+        QRInput::Auto("health check".to_string()),
+        Some(5),
+        Some(2),
+        None,
+    );
+    let (width, height) = bitmap.dims();
+    let mut erasures = image::Bitmap::new(width, height);
+
+    // walk the data modules in order, flipping the first bit of codewords
+    // 0 to 5, and marking codewords 20 to 22 as unreadable
+    let (mut x, mut y) = (width - 1, height - 1);
+    for i in 0..8 * 23 {
+        if i % 8 == 0 && i < 8 * 6 {
+            bitmap.set_bit(x, y, !bitmap.get_bit(x, y).unwrap());
+        }
+        if i >= 8 * 20 {
+            erasures.set_bit(x, y, true);
+        }
+        (x, y) = super::next_data_bit(x, y, 5).unwrap();
+    }
+    // and one bit of the top left format copy
+    bitmap.set_bit(8, 0, !bitmap.get_bit(8, 0).unwrap());
+
+    let health = read_qr_with_erasures(&bitmap, &erasures)?.health;
+    let errors: usize = health.blocks.iter().map(|b| b.errors).sum();
+    let erased: usize = health.blocks.iter().map(|b| b.erasures).sum();
+    // version 5-Q has four blocks of 18 error correction codewords
+    if health.blocks.len() != 4 || errors != 6 || erased != 3 {
+        return Err(format!("{:?}", health.blocks));
+    }
+    if health.format.copies_agree || health.format.bit_errors != 0 || health.margin() != 18 - 4 - 1
+    {
+        return Err(format!("{:?}, margin {}", health.format, health.margin()));
+    }
+
+    // counts past what a block can correct have no margin left
+    let over = BlockHealth {
+        errors: 10,
+        erasures: 3,
+        ..health.blocks[0]
+    };
+    if over.margin() != 0 {
+        return Err(format!("{:?}: margin {}", over, over.margin()));
+    }
+    Ok(())
+}
+
+// random mixes of segments, in every version, level and mask
+// (but not every combination of them, which takes too long)
+#[test]
+fn random_round_trip() -> Result<(), String> {
+    use crate::random::Rng;
+    use badstream::{make_qr, QRInput};
+
+    let alphanum: Vec<char> = tables::ALPHANUM_SET.chars().collect();
+    let bytes: Vec<char> = "abcxyz AZ09,.!?-_/\\\"'\n\t~é€π\u{3042}\u{1f499}"
+        .chars()
+        .collect();
+    let kanji: Vec<char> = "漢字点茗あカ　ΩЖ＼".chars().collect();
+
+    let seed = 0x5EED_0017;
+    let mut rng = Rng::new(seed);
+    for version in 1..=40 {
+        let class = tables::version_to_class(version);
+        // every level goes through every mask, over the versions
+        let mask = (version as u8 - 1) % 8;
+        let level = (version as u8 - 1 + (version as u8 - 1) / 8) % 4;
+        let capacity = 8 * tables::DATA_CODEWORDS[level as usize][version as usize - 1];
+        // leave room for an ECI marker
+        let target = ((capacity - 12) as f32 * rng.float()) as usize;
+
+        let mut segments: Vec<(Mode, String)> = Vec::new();
+        let mut bits = 0;
+        loop {
+            let mode = *rng.pick(&Mode::ALL);
+            let length = 1 + rng.below(30);
+            let data: String = (0..length)
+                .map(|_| match mode {
+                    Mode::Numeric => char::from(b'0' + rng.below(10) as u8),
+                    Mode::AlphaNum => *rng.pick(&alphanum),
+                    Mode::ASCII => *rng.pick(&bytes),
+                    Mode::Kanji => *rng.pick(&kanji),
+                })
+                .collect();
+            let data_bits = match mode {
+                Mode::Numeric => 10 * (length / 3) + [0, 4, 7][length % 3],
+                Mode::AlphaNum => 11 * (length / 2) + 6 * (length % 2),
+                Mode::ASCII => 8 * data.len(),
+                Mode::Kanji => 13 * length,
+            };
+            let cost = 4 + tables::cc_indicator_bit_size(class, mode) + data_bits;
+            if bits + cost > target {
+                break;
+            }
+            bits += cost;
+            segments.push((mode, data));
+        }
+
+        let text: String = segments.iter().map(|(_, data)| data.as_str()).collect();
+        // the optimizer only ever does better than one big byte segment
+        let input = if rng.chance(0.5) && 4 + 16 + 8 * text.len() <= capacity - 12 {
+            QRInput::Auto(text.clone())
+        } else {
+            QRInput::Manual(segments)
+        };
+        let description = format!(
+            "seed {:x}, {}-{} mask {}, {:?}",
+            seed, version, level, mask, input
+        );
+
+        let bitmap = make_qr(input, Some(version), Some(level), Some(mask));
+        let decoded = read_qr(&bitmap).map_err(|e| format!("{}: {}", description, e))?;
+        if decoded.text() != text
+            || (decoded.version, decoded.level, decoded.mask) != (version, level, mask)
+        {
+            return Err(format!("{}: read {:?}", description, decoded.text()));
+        }
+    }
+    Ok(())
+}
+
+// long messages split over several codes, read back in any order
+#[test]
+fn structured_append_round_trip() -> Result<(), String> {
+    use badstream::{make_structured_append, QRInput};
+
+    let text: String = (0..60)
+        .map(|i| {
+            format!(
+                "line {} of the manual \u{1f4d6} \u{6f22}\u{5b57}{}\n",
+                i,
+                i * 7919
+            )
+        })
+        .collect();
+    let mut codes = make_structured_append(QRInput::Auto(text.clone()), Some(10), Some(1), None)?;
+    if !(2..=16).contains(&codes.len()) {
+        return Err(format!("split into {} codes", codes.len()));
+    }
+
+    codes.reverse();
+    let mut parts = Vec::new();
+    for code in &codes {
+        let decoded = read_qr(code)?;
+        if decoded.version > 10 || decoded.structured_append.is_none() {
+            return Err(format!("part {:?}", decoded));
+        }
+        parts.push(decoded);
+    }
+    if join_structured_append(&parts)? != text {
+        return Err("the parts don't add up".to_string());
+    }
+    if join_structured_append(&parts[1..]).is_ok() {
+        return Err("joined an incomplete sequence".to_string());
+    }
+
+    // short messages aren't split at all
+    let single = make_structured_append(QRInput::Auto("short".to_string()), None, None, None)?;
+    if single.len() != 1 || read_qr(&single[0])?.structured_append.is_some() {
+        return Err("a short message was split".to_string());
+    }
+    Ok(())
+}
+
+// gs1 and other fnc1 data, with separators in every mode
+#[test]
+fn decode_fnc1() -> Result<(), String> {
+    use super::bitstream::{make_token_stream, tokens_to_badstream};
+    use badstream::{make_qr, QRInput};
+
+    let gs = Fnc1::SEPARATOR;
+    let gs1 = format!("0109501101020917172512311010A%B{}21XYZ{}3103000189", gs, gs);
+    let manual = vec![
+        (Mode::AlphaNum, format!("AB%{}C", gs)),
+        (Mode::ASCII, format!("x{}y%", gs)),
+    ];
+    for (fnc1, input, text) in [
+        (Fnc1::First, QRInput::Auto(gs1.clone()), gs1.clone()),
+        (
+            Fnc1::Second(b'Z' + 100),
+            QRInput::Manual(manual.clone()),
+            format!("AB%{}Cx{}y%", gs, gs),
+        ),
+    ] {
+        let bitmap = make_qr(QRInput::Fnc1(fnc1, Box::new(input)), None, Some(1), None);
+        let decoded = read_qr(&bitmap)?;
+        if decoded.text() != text || decoded.fnc1() != Some(fnc1) {
+            return Err(format!("read {:?} in {:?}", decoded.text(), decoded.fnc1()));
+        }
+    }
+
+    // "%" and the separator are written as "%%" and "%"
+    let stream = tokens_to_badstream(
+        make_token_stream(manual[..1].to_vec(), None, Some(Fnc1::First)),
+        1,
+    );
+    let segments = badstream_to_segments(&stream, 1)?;
+    if stream[..4] != [0, 1, 0, 1] || stream[4..8] != [0, 0, 1, 0] || segments[0].data.len() != 5 {
+        return Err(format!("wrote {:?}", segments));
+    }
+    Ok(())
+}
@@ -0,0 +1,885 @@
+use super::{decode, image};
+
+/// Projective transforms, for codes that aren't viewed head-on.
+pub mod perspective;
+
+/*
+finding a qr code in an image:
+1. scan the rows for runs of pixels in a 1:1:3:1:1 ratio
+   (dark-light-dark-light-dark), and check each hit vertically
+2. the three finder patterns that form a right isosceles triangle
+   make up the code; the one at the right angle is the top left
+3. the distance between them, measured in modules, gives the version
+4. read the center pixel of every module
+*/
+
+/// A possible finder pattern found in an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FinderPattern {
+    /// Horizontal position of the center, in pixels.
+    pub x: f32,
+    /// Vertical position of the center, in pixels.
+    pub y: f32,
+    /// The estimated width of a module, in pixels.
+    pub module_size: f32,
+    /// The number of scanned rows that the pattern was found in.
+    pub count: usize,
+}
+
+impl FinderPattern {
+    fn distance(&self, other: &Self) -> f32 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+/// Does a series of five run lengths match the 1:1:3:1:1 ratio?
+fn finder_ratio(runs: [usize; 5]) -> bool {
+    let total: usize = runs.iter().sum();
+    if total < 7 {
+        return false;
+    }
+    let module = total as f32 / 7.0;
+    let variance = module / 2.0;
+    runs.iter()
+        .zip([1.0, 1.0, 3.0, 1.0, 1.0])
+        .all(|(&run, modules)| (run as f32 - module * modules).abs() < variance * modules)
+}
+
+/// Measure the five runs through a point, in one direction: `step(i)` gives the
+/// pixel `i` steps away from the point, or `None` when outside the image.
+///
+/// Returns the offset of the center of the middle run, and the run lengths.
+fn cross_check<F>(step: F, max_run: usize) -> Option<(f32, [usize; 5])>
+where
+    F: Fn(isize) -> Option<bool>,
+{
+    let mut runs = [0usize; 5];
+
+    // the middle run, on both sides of the point
+    if step(0) != Some(true) {
+        return None;
+    }
+    let mut back = 0;
+    while step(back - 1) == Some(true) {
+        back -= 1;
+    }
+    let mut forth = 0;
+    while step(forth + 1) == Some(true) {
+        forth += 1;
+    }
+    runs[2] = (forth - back + 1) as usize;
+
+    // the two runs on either side: light, then dark
+    for (run_index, direction, start) in [(1, -1, back), (3, 1, forth)] {
+        let mut position = start;
+        for (offset, color) in [(0, false), (1, true)] {
+            let index = if direction < 0 {
+                run_index - offset
+            } else {
+                run_index + offset
+            };
+            while step(position + direction) == Some(color) {
+                position += direction;
+                runs[index] += 1;
+                if runs[index] > max_run {
+                    return None;
+                }
+            }
+            if runs[index] == 0 {
+                return None;
+            }
+        }
+    }
+
+    if finder_ratio(runs) {
+        Some(((back + forth) as f32 / 2.0, runs))
+    } else {
+        None
+    }
+}
+
+/// Find all the finder patterns in a black-and-white image.
+pub fn find_finder_patterns(image: &image::Bitmap) -> Vec<FinderPattern> {
+    let (width, height) = image.dims();
+    let get = |x: isize, y: isize| {
+        if x < 0 || y < 0 {
+            None
+        } else {
+            image.get_bit(x as usize, y as usize)
+        }
+    };
+
+    let mut output: Vec<FinderPattern> = Vec::new();
+    for y in 0..height {
+        // run lengths of the row, starting with the first dark pixel
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        let mut x = 0;
+        while x < width {
+            let color = image.get_bit(x, y).unwrap();
+            let start = x;
+            while x < width && image.get_bit(x, y).unwrap() == color {
+                x += 1;
+            }
+            if color || !runs.is_empty() {
+                runs.push((start, x - start));
+            }
+        }
+
+        // dark runs are at even indices
+        for window in runs.windows(5).step_by(2) {
+            let lengths = [0, 1, 2, 3, 4].map(|i| window[i].1);
+            if !finder_ratio(lengths) {
+                continue;
+            }
+            let total: usize = lengths.iter().sum();
+            let center_x = window[2].0 + window[2].1 / 2;
+
+            // check the column through the center
+            let Some((dy, vertical)) =
+                cross_check(|i| get(center_x as isize, y as isize + i), total)
+            else {
+                continue;
+            };
+            let center_y = y as f32 + dy;
+
+            // then the row through the center of the column
+            let Some((dx, horizontal)) =
+                cross_check(|i| get(center_x as isize + i, center_y as isize), total)
+            else {
+                continue;
+            };
+
+            let total = (horizontal.iter().sum::<usize>() + vertical.iter().sum::<usize>()) as f32;
+            let found = FinderPattern {
+                x: center_x as f32 + dx + 0.5,
+                y: center_y + 0.5,
+                module_size: total / 14.0,
+                count: 1,
+            };
+
+            // merge with a pattern that's already been found
+            match output.iter_mut().find(|p| {
+                p.distance(&found) < 2.0 * p.module_size.max(found.module_size)
+                    && (p.module_size - found.module_size).abs() < p.module_size.max(1.0)
+            }) {
+                Some(p) => {
+                    let n = p.count as f32;
+                    p.x = (p.x * n + found.x) / (n + 1.0);
+                    p.y = (p.y * n + found.y) / (n + 1.0);
+                    p.module_size = (p.module_size * n + found.module_size) / (n + 1.0);
+                    p.count += 1;
+                }
+                None => output.push(found),
+            }
+        }
+    }
+    output
+}
+
+/// Pick the three finder patterns that are most likely to make up a QR code.
+///
+/// Returns them in the order top left, top right, bottom left,
+/// relative to the orientation of the code.
+pub fn locate_symbol(patterns: &[FinderPattern]) -> Option<[FinderPattern; 3]> {
+    scored_triples(patterns)
+        .first()
+        .map(|&(_, triple, _)| triple)
+}
+
+// every three patterns that could make up a code, with their indices
+// in `patterns`, from the best triangle to the worst. the top left pattern
+// of a code is only paired with its nearest neighbors of about the same size,
+// and a triangle with another solid pattern inside it can't be a code
+fn scored_triples(patterns: &[FinderPattern]) -> Vec<([usize; 3], [FinderPattern; 3], f32)> {
+    const NEIGHBORS: usize = 8;
+    let mut triples = Vec::new();
+    for (i, &a) in patterns.iter().enumerate() {
+        let mut candidates: Vec<usize> = (0..patterns.len())
+            .filter(|&j| {
+                let size = patterns[j].module_size;
+                j != i && size.max(a.module_size) <= 1.5 * size.min(a.module_size)
+            })
+            .collect();
+        candidates.sort_by(|&j, &k| {
+            a.distance(&patterns[j])
+                .total_cmp(&a.distance(&patterns[k]))
+        });
+        // lookalikes don't count towards the limit, or the data of a big
+        // code could push its real corners out
+        let mut neighbors = Vec::new();
+        let mut solid = 0;
+        for j in candidates {
+            if solid == NEIGHBORS {
+                break;
+            }
+            neighbors.push(j);
+            solid += usize::from(is_solid(&patterns[j]));
+        }
+
+        for (n, &j) in neighbors.iter().enumerate() {
+            for &k in &neighbors[n + 1..] {
+                let Some((triple, score)) = score_triple(a, patterns[j], patterns[k]) else {
+                    continue;
+                };
+                // every triangle is found from its top left corner
+                if triple[0] != a {
+                    continue;
+                }
+                let blocked = patterns.iter().enumerate().any(|(m, p)| {
+                    ![i, j, k].contains(&m) && is_solid(p) && inside_triangle(p, &triple)
+                });
+                if !blocked {
+                    triples.push(([i, j, k], triple, score));
+                }
+            }
+        }
+    }
+    triples.sort_by(|a, b| a.2.total_cmp(&b.2));
+    triples
+}
+
+// a real finder pattern is found on about as many rows as the three modules
+// of its center are tall. lookalikes in the data of a code usually only have
+// the right ratio on a row or two
+fn is_solid(pattern: &FinderPattern) -> bool {
+    pattern.count as f32 >= 2.0 * pattern.module_size
+}
+
+// is a pattern inside a triangle of three others, and clear of its sides?
+fn inside_triangle(pattern: &FinderPattern, [tl, tr, bl]: &[FinderPattern; 3]) -> bool {
+    // the pattern's position along the two legs, as fractions of their length
+    let (ux, uy, vx, vy) = (tr.x - tl.x, tr.y - tl.y, bl.x - tl.x, bl.y - tl.y);
+    let (px, py) = (pattern.x - tl.x, pattern.y - tl.y);
+    let determinant = ux * vy - uy * vx;
+    let u = (px * vy - py * vx) / determinant;
+    let v = (ux * py - uy * px) / determinant;
+    // a finder pattern is 7 modules wide, so anything closer than that
+    // to a side overlaps it, and is more likely a duplicate
+    let margin = 7.0 * pattern.module_size / tl.distance(tr).min(tl.distance(bl));
+    u > margin && v > margin && u + v < 1.0 - margin
+}
+
+/// How far three finder patterns are from forming a right isosceles triangle,
+/// and their order (top left, top right, bottom left). Lower is better.
+fn score_triple(
+    a: FinderPattern,
+    b: FinderPattern,
+    c: FinderPattern,
+) -> Option<([FinderPattern; 3], f32)> {
+    // the patterns of a single code are all about the same size
+    let sizes = [a.module_size, b.module_size, c.module_size];
+    let (min, max) = (
+        sizes.iter().copied().fold(f32::MAX, f32::min),
+        sizes.iter().copied().fold(0.0, f32::max),
+    );
+    if max > 1.5 * min {
+        return None;
+    }
+
+    // the top left pattern is the one opposite the longest side
+    let (tl, mut tr, mut bl) = {
+        let (ab, bc, ca) = (a.distance(&b), b.distance(&c), c.distance(&a));
+        if bc >= ab && bc >= ca {
+            (a, b, c)
+        } else if ca >= ab && ca >= bc {
+            (b, c, a)
+        } else {
+            (c, a, b)
+        }
+    };
+
+    // the top right pattern is clockwise from the bottom left one
+    // (with the y axis pointing down)
+    let cross = (tr.x - tl.x) * (bl.y - tl.y) - (tr.y - tl.y) * (bl.x - tl.x);
+    if cross < 0.0 {
+        (tr, bl) = (bl, tr);
+    }
+
+    let (leg1, leg2, hypotenuse) = (tl.distance(&tr), tl.distance(&bl), tr.distance(&bl));
+    // the finder patterns can't overlap
+    if leg1.min(leg2) < 7.0 * max {
+        return None;
+    }
+    let score = (leg1 - leg2).abs() / leg1.max(leg2)
+        + (hypotenuse.powi(2) - leg1.powi(2) - leg2.powi(2)).abs() / hypotenuse.powi(2);
+    if score > 0.5 {
+        return None;
+    }
+    Some(([tl, tr, bl], score))
+}
+
+/// Estimate the width of a code in modules from its finder patterns.
+pub fn estimate_dimension(finders: &[FinderPattern; 3]) -> usize {
+    let [tl, tr, bl] = finders;
+    let module_size = (tl.module_size + tr.module_size + bl.module_size) / 3.0;
+    let modules = (tl.distance(tr) + tl.distance(bl)) / (2.0 * module_size);
+
+    // the finder centers are 3.5 modules in from the edges,
+    // and the width of a code is always 4 * version + 17,
+    // so snap to the nearest width of that form
+    let nearest = (((modules + 7.0 - 1.0) / 4.0).round() as usize * 4 + 1).clamp(21, 177);
+    let version = super::size_to_version(nearest).expect("width is always a valid version");
+    super::version_to_max_index(version).expect("version is always valid") + 1
+}
+
+/// Read the modules of a QR code of a known dimension from an image.
+///
+/// The grid is laid out by the three finder patterns, which are assumed to lie
+/// 3.5 modules in from the corners of the code. Every module is read
+/// by a majority vote of five pixels around its center.
+pub fn sample_grid(
+    image: &image::Bitmap,
+    finders: &[FinderPattern; 3],
+    dimension: usize,
+) -> image::Bitmap {
+    sample_with(image, dimension, affine_transform(finders, dimension))
+}
+
+/// The image position of the module coordinates `(u, v)` in a QR code located
+/// by three finder patterns, assuming it's seen head-on: the grid used by
+/// [sample_grid].
+pub fn affine_transform(
+    finders: &[FinderPattern; 3],
+    dimension: usize,
+) -> impl Fn(f32, f32) -> (f32, f32) + '_ {
+    let [tl, tr, bl] = finders;
+    let span = dimension as f32 - 7.0;
+    move |u, v| {
+        let (u, v) = ((u - 3.5) / span, (v - 3.5) / span);
+        (
+            tl.x + u * (tr.x - tl.x) + v * (bl.x - tl.x),
+            tl.y + u * (tr.y - tl.y) + v * (bl.y - tl.y),
+        )
+    }
+}
+
+/// Read a grid of modules, with `transform` mapping module coordinates to
+/// pixel coordinates.
+pub fn sample_with<F>(image: &image::Bitmap, dimension: usize, transform: F) -> image::Bitmap
+where
+    F: Fn(f32, f32) -> (f32, f32),
+{
+    let mut output = image::Bitmap::new(dimension, dimension);
+    let get = |(x, y): (f32, f32)| {
+        x >= 0.0 && y >= 0.0 && image.get_bit(x as usize, y as usize) == Some(true)
+    };
+
+    for v in 0..dimension {
+        for u in 0..dimension {
+            let (u, v) = (u as f32 + 0.5, v as f32 + 0.5);
+            let votes = [(0.0, 0.0), (-0.2, 0.0), (0.2, 0.0), (0.0, -0.2), (0.0, 0.2)]
+                .into_iter()
+                .filter(|(du, dv)| get(transform(u + du, v + dv)))
+                .count();
+            output.set_bit(u as usize, v as usize, votes >= 3);
+        }
+    }
+    output
+}
+
+/// Find a QR code in a black-and-white image, and read its modules into a
+/// bitmap that can be passed to [read_qr](decode::read_qr).
+///
+/// If no code is found, the image is searched again with light and dark
+/// swapped, and the modules of a light-on-dark code are returned dark on light.
+pub fn sample_qr(image: &image::Bitmap) -> Result<image::Bitmap, String> {
+    let finders = locate_in_image(image);
+    if let Ok(finders) = finders {
+        return Ok(sample_symbol(image, &finders));
+    }
+    let inverted = image.clone().invert();
+    let finders = locate_in_image(&inverted).or(finders)?;
+    Ok(sample_symbol(&inverted, &finders))
+}
+
+// find_finder_patterns() followed by locate_symbol(), except that the
+// first triangle with timing patterns between its corners wins. the data
+// of a big code has finder-like shapes of its own, and when the code is
+// seen at an angle, they can make a better triangle than the real ones
+fn locate_in_image(image: &image::Bitmap) -> Result<[FinderPattern; 3], String> {
+    let patterns = find_finder_patterns(image);
+    let triples = scored_triples(&patterns);
+    triples
+        .iter()
+        .find(|(_, finders, _)| {
+            roughly_has_timing_patterns(image, finders)
+                && has_timing_patterns(&sample_symbol(image, finders))
+        })
+        .or(triples.first())
+        .map(|&(_, finders, _)| finders)
+        .ok_or(format!(
+            "couldn't find three matching finder patterns ({} candidates)",
+            patterns.len()
+        ))
+}
+
+/// The width of a QR code located by three finder patterns, in modules.
+///
+/// For larger codes, the estimate from the finder patterns is easily
+/// a version or two off, so it's corrected using the version information
+/// if that can be read. The version information only counts if it's read
+/// the same from a perspective-corrected grid of the width it gives, since
+/// misreads are common when the code is seen at an angle.
+pub fn symbol_dimension(image: &image::Bitmap, finders: &[FinderPattern; 3]) -> usize {
+    let estimate = estimate_dimension(finders);
+    let read = sample_grid(image, finders, estimate)
+        .qr_version_info()
+        .map(|info| 4 * info.version as usize + 17);
+
+    // then the estimate and the widths around it, in case the plain grid
+    // was too far off to read. version information starts at version 7
+    read.into_iter()
+        .chain([
+            estimate,
+            estimate + 4,
+            estimate - 4,
+            estimate + 8,
+            estimate - 8,
+        ])
+        .filter(|dimension| (45..=177).contains(dimension))
+        .find(|&dimension| {
+            perspective::sample_grid_perspective(image, finders, dimension)
+                .qr_version_info()
+                .is_some_and(|info| 4 * info.version as usize + 17 == dimension)
+        })
+        .unwrap_or(estimate)
+}
+
+/// Read a QR code located by three finder patterns.
+pub fn sample_symbol(image: &image::Bitmap, finders: &[FinderPattern; 3]) -> image::Bitmap {
+    perspective::sample_grid_perspective(image, finders, symbol_dimension(image, finders))
+}
+
+/// Find and decode a QR code in a black-and-white image.
+///
+/// Light-on-dark and mirror-image codes are read as well.
+pub fn read_qr_from_image(image: &image::Bitmap) -> Result<decode::Decoded, String> {
+    let error = match read_in_image(image) {
+        Ok(decoded) => return Ok(decoded),
+        Err(error) => error,
+    };
+
+    // a light-on-dark code has light finder patterns, so they won't be found
+    let inverted = image.clone().invert();
+    let decoded = read_in_image(&inverted).map_err(|_| error)?;
+    Ok(decode::Decoded {
+        inverted: !decoded.inverted,
+        ..decoded
+    })
+}
+
+// locate_in_image() followed by read_symbol(), except that if the code
+// doesn't decode, the next triangle with timing patterns gets a try, and
+// so on. a triangle with a fake corner can have most of its timing
+// patterns right, if the fake is on the edge of the real code
+fn read_in_image(image: &image::Bitmap) -> Result<decode::Decoded, String> {
+    let patterns = find_finder_patterns(image);
+    let triples = scored_triples(&patterns);
+    let mut first_error = None;
+    for (_, finders, _) in &triples {
+        if !roughly_has_timing_patterns(image, finders) {
+            continue;
+        }
+        let sampled = sample_symbol(image, finders);
+        if !has_timing_patterns(&sampled) {
+            continue;
+        }
+        match read_sampled_symbol(image, finders, &sampled) {
+            Ok(decoded) => return Ok(decoded),
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    if let Some(error) = first_error {
+        return Err(error);
+    }
+
+    // nothing looks much like a code, so go with the best triangle
+    let (_, finders, _) = triples.first().ok_or(format!(
+        "couldn't find three matching finder patterns ({} candidates)",
+        patterns.len()
+    ))?;
+    read_symbol(image, finders)
+}
+
+/// Decode a QR code located by three finder patterns.
+///
+/// If the perspective-corrected grid can't be decoded, the plain affine grid
+/// is tried as well, followed by the neighboring versions.
+pub fn read_symbol(
+    image: &image::Bitmap,
+    finders: &[FinderPattern; 3],
+) -> Result<decode::Decoded, String> {
+    read_sampled_symbol(image, finders, &sample_symbol(image, finders))
+}
+
+// read_symbol(), starting from the grid that sample_symbol() gave
+fn read_sampled_symbol(
+    image: &image::Bitmap,
+    finders: &[FinderPattern; 3],
+    sampled: &image::Bitmap,
+) -> Result<decode::Decoded, String> {
+    let sampled_dimension = sampled.dims().0;
+    let mut first_error = None;
+    for dimension in [
+        sampled_dimension,
+        sampled_dimension + 4,
+        sampled_dimension.saturating_sub(4),
+    ] {
+        if !(21..=177).contains(&dimension) {
+            continue;
+        }
+        let resampled;
+        let perspective_grid = if dimension == sampled_dimension {
+            sampled
+        } else {
+            resampled = perspective::sample_grid_perspective(image, finders, dimension);
+            &resampled
+        };
+        for grid in [perspective_grid, &sample_grid(image, finders, dimension)] {
+            match decode::read_qr(grid) {
+                Ok(decoded) => return Ok(decoded),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+    }
+    Err(first_error.unwrap_or("couldn't sample a valid QR code".to_string()))
+}
+
+/// A QR code found in an image by [find_symbols].
+#[derive(Clone)]
+pub struct Symbol {
+    /// The finder patterns: top left, top right, bottom left.
+    pub finders: [FinderPattern; 3],
+    /// The smallest rectangle of pixels that contains the code,
+    /// as `(left, top, right, bottom)`. The right and bottom are exclusive.
+    pub bounds: (usize, usize, usize, usize),
+    /// The modules of the code, as read from the image.
+    pub bitmap: image::Bitmap,
+    /// The result of decoding the code.
+    pub decoded: Result<decode::Decoded, String>,
+}
+
+/// Find, sample and decode every QR code in a black-and-white image.
+///
+/// Codes that can be located but not decoded are still returned, with the
+/// error in [Symbol::decoded]. The codes are given in the order they're
+/// found, which is roughly from most to least clearly visible, followed by
+/// any light-on-dark codes.
+pub fn find_symbols(image: &image::Bitmap) -> Vec<Symbol> {
+    let mut output = find_symbols_as_is(image);
+    for mut symbol in find_symbols_as_is(&image.clone().invert()) {
+        if let Ok(decoded) = &mut symbol.decoded {
+            decoded.inverted = !decoded.inverted;
+        }
+        output.push(symbol);
+    }
+    output
+}
+
+// find_symbols(), for dark-on-light codes only
+fn find_symbols_as_is(image: &image::Bitmap) -> Vec<Symbol> {
+    let patterns = find_finder_patterns(image);
+    let triples = scored_triples(&patterns);
+
+    // when codes are laid out in a grid, patterns from neighboring codes
+    // form triangles just as good as the real ones, but the modules between
+    // them don't look anything like a QR code. so go from the best triangle
+    // to the worst, keeping the ones with timing patterns where they should be
+    let mut used = vec![false; patterns.len()];
+    let mut output = Vec::new();
+    for (indices, finders, _) in triples {
+        if indices.iter().any(|&i| used[i]) || !roughly_has_timing_patterns(image, &finders) {
+            continue;
+        }
+        let bitmap = sample_symbol(image, &finders);
+        if !has_timing_patterns(&bitmap) {
+            continue;
+        }
+        for i in indices {
+            used[i] = true;
+        }
+        output.push(Symbol {
+            finders,
+            bounds: bounding_box(image, &finders, bitmap.dims().0),
+            decoded: read_sampled_symbol(image, &finders, &bitmap),
+            bitmap,
+        });
+    }
+    output
+}
+
+// are at least three quarters of the timing pattern modules right?
+fn has_timing_patterns(bitmap: &image::Bitmap) -> bool {
+    let dimension = bitmap.dims().0;
+    let mut correct = 0;
+    for i in 8..dimension - 8 {
+        let dark = i % 2 == 0;
+        correct += usize::from(bitmap.get_bit(i, 6) == Some(dark));
+        correct += usize::from(bitmap.get_bit(6, i) == Some(dark));
+    }
+    4 * correct >= 3 * 2 * (dimension - 16)
+}
+
+// a cheap stand-in for has_timing_patterns(&sample_symbol(..)), which has
+// to find the width of the code first and can sample it eight times over.
+// a projective transform keeps straight lines straight, so the timing
+// patterns run in a line between the inner corners of the finder patterns,
+// however the code is seen. along those lines, at least three quarters of
+// the pixels should be in runs of about a module each, whatever the width
+fn roughly_has_timing_patterns(image: &image::Bitmap, finders: &[FinderPattern; 3]) -> bool {
+    let [tl, tr, bl] = finders;
+    let direction = |from: &FinderPattern, to: &FinderPattern| {
+        let length = from.distance(to);
+        ((to.x - from.x) / length, (to.y - from.y) / length)
+    };
+    let (across, down) = (direction(tl, tr), direction(tl, bl));
+    // three modules across and down from the center of a finder pattern
+    let corner = |p: &FinderPattern, u: f32, v: f32| {
+        let step = 3.0 * p.module_size;
+        (
+            p.x + step * (u * across.0 + v * down.0),
+            p.y + step * (u * across.1 + v * down.1),
+        )
+    };
+    let start = corner(tl, 1.0, 1.0);
+    runs_of_one_module(image, start, corner(tr, -1.0, 1.0), tl, tr)
+        && runs_of_one_module(image, start, corner(bl, 1.0, -1.0), tl, bl)
+}
+
+// are three quarters of the pixels on a line in runs of about a module?
+// the module size changes smoothly from one end of the line to the other
+fn runs_of_one_module(
+    image: &image::Bitmap,
+    (x0, y0): (f32, f32),
+    (x1, y1): (f32, f32),
+    from: &FinderPattern,
+    to: &FinderPattern,
+) -> bool {
+    let length = (x1 - x0).hypot(y1 - y0);
+    // a few samples per module, even at the smaller end. patterns far off
+    // the image can give a line that's NaN or absurdly long
+    let samples = (4.0 * length / from.module_size.min(to.module_size)).ceil();
+    if !(1.0..=1e6).contains(&samples) {
+        return false;
+    }
+    let samples = samples as usize;
+    let get = |i: usize| {
+        let t = i as f32 / samples as f32;
+        let (x, y) = (x0 + t * (x1 - x0), y0 + t * (y1 - y0));
+        x >= 0.0 && y >= 0.0 && image.get_bit(x as usize, y as usize) == Some(true)
+    };
+
+    let mut good = 0;
+    let mut start = 0;
+    let mut color = get(0);
+    for i in 1..=samples + 1 {
+        let next = i <= samples && get(i);
+        if i <= samples && next == color {
+            continue;
+        }
+        let (run, middle) = (i - start, (start + i) as f32 / 2.0 / samples as f32);
+        let module = from.module_size + middle * (to.module_size - from.module_size);
+        let pixels = run as f32 * length / samples as f32;
+        if (0.5 * module..1.5 * module).contains(&pixels) {
+            good += run;
+        }
+        (start, color) = (i, next);
+    }
+    4 * good >= 3 * (samples + 1)
+}
+
+// the corners of the code, ignoring perspective, but with a module of margin
+fn bounding_box(
+    image: &image::Bitmap,
+    finders: &[FinderPattern; 3],
+    dimension: usize,
+) -> (usize, usize, usize, usize) {
+    let transform = affine_transform(finders, dimension);
+    let size = dimension as f32;
+    let corners = [
+        (-1.0, -1.0),
+        (size + 1.0, -1.0),
+        (-1.0, size + 1.0),
+        (size + 1.0, size + 1.0),
+    ]
+    .map(|(u, v)| transform(u, v));
+    let (width, height) = image.dims();
+    let clamp = |value: f32, max: usize| (value.max(0.0) as usize).min(max);
+    (
+        clamp(
+            corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor(),
+            width,
+        ),
+        clamp(
+            corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor(),
+            height,
+        ),
+        clamp(
+            corners.iter().map(|c| c.0).fold(f32::MIN, f32::max).ceil(),
+            width,
+        ),
+        clamp(
+            corners.iter().map(|c| c.1).fold(f32::MIN, f32::max).ceil(),
+            height,
+        ),
+    )
+}
+
+// the output of the encoder, scaled up to a non-integer module size
+#[test]
+fn sample_scaled() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    for (text, version) in [
+        ("sampling", 1),
+        ("sampling", 5),
+        ("sampling", 8),
+        ("sampling", 24),
+    ] {
+        let bitmap = make_qr(
+            QRInput::Auto(text.to_string()),
+            Some(version),
+            Some(1),
+            Some(3),
+        );
+        for width in [0, 300, 777] {
+            let scaled = bitmap.clone().add_border().scale(Some(width));
+            let sampled = sample_qr(&scaled)?;
+            if sampled.debug_bits() != bitmap.debug_bits() {
+                return Err(format!(
+                    "version {} at width {}: sampled incorrectly",
+                    version, width
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// a mirrored, light-on-dark code on a dark background
+#[test]
+fn sample_reversed() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    let image = make_qr(QRInput::Auto("engraved".to_string()), None, None, None)
+        .transpose()
+        .invert()
+        .add_border()
+        .scale(Some(333));
+    let decoded = read_qr_from_image(&image)?;
+    if decoded.text() != "engraved" || !decoded.mirrored || !decoded.inverted {
+        return Err(format!("read {:?}", decoded));
+    }
+    Ok(())
+}
+
+// a sheet of codes in a grid, plus one on its own
+#[test]
+fn find_many() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    let mut sheet = image::Bitmap::new(900, 700);
+    let mut expected = Vec::new();
+    let mut paste = |text: String, version: u32, x: usize, y: usize, width: usize| {
+        let code = make_qr(QRInput::Auto(text.clone()), Some(version), Some(1), None)
+            .add_border()
+            .scale(Some(width));
+        let (code_width, code_height) = code.dims();
+        // the dark pixels of the code itself, without its quiet zone
+        let mut inner = (usize::MAX, usize::MAX, 0, 0);
+        for dy in 0..code_height {
+            for dx in 0..code_width {
+                let dark = code.get_bit(dx, dy).unwrap();
+                sheet.set_bit(x + dx, y + dy, dark);
+                if dark {
+                    inner.0 = inner.0.min(x + dx);
+                    inner.1 = inner.1.min(y + dy);
+                    inner.2 = inner.2.max(x + dx + 1);
+                    inner.3 = inner.3.max(y + dy + 1);
+                }
+            }
+        }
+        expected.push((text, (x, y, x + width, y + width), inner));
+    };
+    for row in 0..3 {
+        for column in 0..3 {
+            let text = format!("label {}-{}", row, column);
+            paste(text, 2, 20 + 200 * column, 20 + 200 * row, 180);
+        }
+    }
+    paste("the odd one out".to_string(), 7, 640, 200, 240);
+
+    let symbols = find_symbols(&sheet);
+    if symbols.len() != expected.len() {
+        return Err(format!("found {} codes", symbols.len()));
+    }
+    for (text, (left, top, right, bottom), inner) in expected {
+        let found = symbols
+            .iter()
+            .find(|s| s.decoded.as_ref().is_ok_and(|d| d.text() == text));
+        let Some(Symbol { bounds, .. }) = found else {
+            return Err(format!("didn't find {:?}", text));
+        };
+        // the box should cover the code, but not much of its quiet zone
+        if bounds.0 < left || bounds.1 < top || bounds.2 > right || bounds.3 > bottom {
+            return Err(format!("{:?} has bounds {:?}", text, bounds));
+        }
+        if bounds.0 > inner.0 || bounds.1 > inner.1 || bounds.2 < inner.2 || bounds.3 < inner.3 {
+            return Err(format!(
+                "{:?} has bounds {:?}, but covers {:?}",
+                text, bounds, inner
+            ));
+        }
+    }
+    Ok(())
+}
+
+// a sheet of three dozen codes, with their quiet zones touching, shouldn't
+// take long to search. patterns from neighboring codes make tens of thousands
+// of triangles, and only a few are real
+#[test]
+fn find_many_quickly() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    let mut sheet = image::Bitmap::new(1240, 1240);
+    for row in 0..6 {
+        for column in 0..6 {
+            let code = make_qr(
+                QRInput::Auto(format!("sheet {}-{}", row, column)),
+                Some(2),
+                Some(1),
+                None,
+            )
+            .add_border()
+            .scale(Some(200));
+            let (width, height) = code.dims();
+            for dy in 0..height {
+                for dx in 0..width {
+                    let dark = code.get_bit(dx, dy).unwrap();
+                    sheet.set_bit(20 + 200 * column + dx, 20 + 200 * row + dy, dark);
+                }
+            }
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let symbols = find_symbols(&sheet);
+    let elapsed = start.elapsed();
+    let decoded = symbols.iter().filter(|s| s.decoded.is_ok()).count();
+    if symbols.len() != 36 || decoded != 36 {
+        return Err(format!(
+            "found {} codes, decoded {}",
+            symbols.len(),
+            decoded
+        ));
+    }
+    // generous, for unoptimized builds
+    if elapsed > std::time::Duration::from_secs(5) {
+        return Err(format!("took {:?}", elapsed));
+    }
+    Ok(())
+}
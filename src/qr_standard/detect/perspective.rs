@@ -0,0 +1,426 @@
+// the finder patterns only give three points, which is enough for an affine
+// transform (parallelograms) but not a projective one (any quadrilateral).
+// the bottom right alignment pattern gives the fourth point, and for larger
+// versions, the alignment patterns divide the code into cells that each get
+// a transform of their own. this also evens out some curvature
+use super::{affine_transform, sample_with, FinderPattern};
+use crate::{image, qr_standard::tables};
+
+/// A projective transform of the plane:
+/// `(x, y) -> ((ax + by + c) / (gx + hy + 1), (dx + ey + f) / (gx + hy + 1))`.
+#[derive(Clone, Copy, Debug)]
+pub struct Perspective([f64; 8]);
+
+impl Perspective {
+    /// The transform that maps each of the four points in `from`
+    /// to the corresponding point in `to`.
+    ///
+    /// Returns `None` if three of the points lie on a line.
+    pub fn from_points(from: [(f32, f32); 4], to: [(f32, f32); 4]) -> Option<Self> {
+        Self::fit(&from, &to)
+    }
+
+    /// The transform that maps the points in `from` as closely as possible
+    /// (by least squares) to the corresponding points in `to`. With exactly
+    /// four points, this is the same as [Perspective::from_points].
+    ///
+    /// Returns `None` if there aren't enough points in general position.
+    pub fn fit(from: &[(f32, f32)], to: &[(f32, f32)]) -> Option<Self> {
+        // two linear equations per pair of points, in the eight unknowns,
+        // gathered into the normal equations
+        let mut rows = [[0f64; 9]; 8];
+        for (&(x, y), &(u, v)) in from.iter().zip(to) {
+            let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+            for equation in [
+                [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u],
+                [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v],
+            ] {
+                for (row, &a) in rows.iter_mut().zip(&equation) {
+                    for (value, &b) in row.iter_mut().zip(&equation) {
+                        *value += a * b;
+                    }
+                }
+            }
+        }
+
+        // gaussian elimination with partial pivoting
+        for column in 0..8 {
+            let pivot = (column..8)
+                .max_by(|&a, &b| rows[a][column].abs().total_cmp(&rows[b][column].abs()))?;
+            if rows[pivot][column].abs() < 1e-9 {
+                return None;
+            }
+            rows.swap(column, pivot);
+            let pivot_row = rows[column];
+            for (index, row) in rows.iter_mut().enumerate() {
+                if index != column {
+                    let factor = row[column] / pivot_row[column];
+                    for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(column) {
+                        *value -= factor * pivot_value;
+                    }
+                }
+            }
+        }
+
+        let mut output = [0f64; 8];
+        for (i, value) in output.iter_mut().enumerate() {
+            *value = rows[i][8] / rows[i][i];
+        }
+        Some(Perspective(output))
+    }
+
+    /// Apply the transform to a point.
+    pub fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f, g, h] = self.0;
+        let (x, y) = (x as f64, y as f64);
+        let w = g * x + h * y + 1.0;
+        (
+            ((a * x + b * y + c) / w) as f32,
+            ((d * x + e * y + f) / w) as f32,
+        )
+    }
+}
+
+/// Measure the light-dark-light runs through a dark point, in one direction:
+/// `step(i)` gives the pixel `i` steps away from the point. Each run has to be
+/// about a module wide, and the runs have to be bounded by dark pixels.
+///
+/// Returns the offset of the center of the dark run, and the width of the
+/// three runs together (three modules).
+fn cross_check_alignment<F>(step: F, module_size: f32) -> Option<(f32, f32)>
+where
+    F: Fn(isize) -> Option<bool>,
+{
+    let max_run = (2.0 * module_size).ceil() as isize;
+    let fits = |run: isize| (run as f32) > module_size / 2.0 && run <= max_run;
+
+    if step(0) != Some(true) {
+        return None;
+    }
+    let (mut back, mut forth) = (0, 0);
+    while step(back - 1) == Some(true) && -back < max_run {
+        back -= 1;
+    }
+    while step(forth + 1) == Some(true) && forth < max_run {
+        forth += 1;
+    }
+    if !fits(forth - back + 1) {
+        return None;
+    }
+
+    // the light ring on both sides, followed by the dark outer ring
+    let mut width = forth - back + 1;
+    for (start, direction) in [(back, -1), (forth, 1)] {
+        let mut run = 0;
+        while step(start + direction * (run + 1)) == Some(false) && run < max_run {
+            run += 1;
+        }
+        if !fits(run) || step(start + direction * (run + 1)) != Some(true) {
+            return None;
+        }
+        width += run;
+    }
+    Some(((back + forth) as f32 / 2.0, width as f32))
+}
+
+/// Search the neighborhood of a point for the center of an alignment pattern.
+/// `axes` are the steps in the image from one module to the next, across and
+/// down, near the point. The search covers a square reaching `reach` modules
+/// out on every side.
+///
+/// Candidates are found as light-dark-light runs of about a module each, both
+/// horizontally and vertically, and then checked module by module against the
+/// whole pattern. If there's more than one match, the closest one wins.
+/// Returns `None` if nothing is found.
+pub fn find_alignment_pattern(
+    image: &image::Bitmap,
+    center: (f32, f32),
+    axes: [(f32, f32); 2],
+    reach: f32,
+) -> Option<(f32, f32)> {
+    let module_size = (axes[0].0.hypot(axes[0].1) + axes[1].0.hypot(axes[1].1)) / 2.0;
+    let get = |x: isize, y: isize| {
+        if x < 0 || y < 0 {
+            None
+        } else {
+            image.get_bit(x as usize, y as usize)
+        }
+    };
+
+    // a degenerate fit can leave the search anywhere, or of any size
+    let (width, height) = image.dims();
+    let reach = reach * module_size;
+    let in_bounds = module_size.is_finite()
+        && module_size > 0.0
+        && (0.0..=width.max(height) as f32).contains(&reach)
+        && (0.0..width as f32).contains(&center.0)
+        && (0.0..height as f32).contains(&center.1);
+    if !in_bounds {
+        return None;
+    }
+    let reach = reach.ceil() as isize;
+    let (cx, cy) = (center.0 as isize, center.1 as isize);
+
+    // (x, y, module size, number of hits)
+    let mut candidates: Vec<(f32, f32, f32, usize)> = Vec::new();
+    for y in (cy - reach)..=(cy + reach) {
+        for x in (cx - reach)..=(cx + reach) {
+            // only check each dark run once, from its first pixel
+            if get(x, y) != Some(true) || get(x - 1, y) == Some(true) {
+                continue;
+            }
+            let mut end = x;
+            while get(end + 1, y) == Some(true) {
+                end += 1;
+            }
+            let x = x + (end - x) / 2;
+
+            let Some((dx, width)) = cross_check_alignment(|i| get(x + i, y), module_size) else {
+                continue;
+            };
+            let x = x as f32 + dx;
+            let Some((dy, height)) = cross_check_alignment(|i| get(x as isize, y + i), module_size)
+            else {
+                continue;
+            };
+            let found = (x + 0.5, y as f32 + dy + 0.5, (width + height) / 6.0);
+
+            match candidates
+                .iter_mut()
+                .find(|c| (c.0 - found.0).hypot(c.1 - found.1) < module_size)
+            {
+                Some(c) => {
+                    let n = c.3 as f32;
+                    c.0 = (c.0 * n + found.0) / (n + 1.0);
+                    c.1 = (c.1 * n + found.1) / (n + 1.0);
+                    c.2 = (c.2 * n + found.2) / (n + 1.0);
+                    c.3 += 1;
+                }
+                None => candidates.push((found.0, found.1, found.2, 1)),
+            }
+        }
+    }
+
+    // runs like these are common in the data, but the full pattern isn't.
+    // the fit can be a fair bit off on scale as well as position, so the
+    // pattern is checked at the module size measured from the runs
+    let matches = |c: &&(f32, f32, f32, usize)| {
+        let scale = c.2 / module_size;
+        let mut misses = 0;
+        for dv in -2i32..=2 {
+            for du in -2i32..=2 {
+                let dark = du.abs().max(dv.abs()) != 1;
+                let (du, dv) = (du as f32 * scale, dv as f32 * scale);
+                let x = c.0 + du * axes[0].0 + dv * axes[1].0;
+                let y = c.1 + du * axes[0].1 + dv * axes[1].1;
+                if get(x.floor() as isize, y.floor() as isize) != Some(dark) {
+                    misses += 1;
+                }
+            }
+        }
+        misses <= 1
+    };
+    let distance = |c: &&(f32, f32, f32, usize)| (c.0 - center.0).hypot(c.1 - center.1);
+    candidates
+        .iter()
+        .filter(matches)
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .map(|c| (c.0, c.1))
+}
+
+/// Read the modules of a QR code from an image, correcting for perspective
+/// (and some curvature) using its alignment patterns.
+///
+/// Falls back to [sample_grid](super::sample_grid) for version 1, which has no alignment patterns.
+pub fn sample_grid_perspective(
+    image: &image::Bitmap,
+    finders: &[FinderPattern; 3],
+    dimension: usize,
+) -> image::Bitmap {
+    sample_with(image, dimension, grid_transform(image, finders, dimension))
+}
+
+/// The mapping from module coordinates to pixel coordinates that's used by
+/// [sample_grid_perspective]. Module `(x, y)` is centered at `(x + 0.5, y + 0.5)`.
+pub fn grid_transform<'a>(
+    image: &image::Bitmap,
+    finders: &'a [FinderPattern; 3],
+    dimension: usize,
+) -> Box<dyn Fn(f32, f32) -> (f32, f32) + 'a> {
+    let version = (dimension as u32 - 17) / 4;
+    if version < 2 {
+        return Box::new(affine_transform(finders, dimension));
+    }
+    let size = dimension as f32;
+    let [tl, tr, bl] = finders;
+    let corners = [(3.5, 3.5), (size - 3.5, 3.5), (3.5, size - 3.5)];
+    let mut points = [(tl.x, tl.y), (tr.x, tr.y), (bl.x, bl.y), (0.0, 0.0)];
+
+    // start off with a parallelogram, completing the fourth corner
+    points[3] = (tr.x + bl.x - tl.x, tr.y + bl.y - tl.y);
+    let Some(affine) = Perspective::from_points(
+        [corners[0], corners[1], corners[2], (size - 3.5, size - 3.5)],
+        points,
+    ) else {
+        return Box::new(affine_transform(finders, dimension));
+    };
+
+    let indices = tables::AP_COORD_INDICES[version as usize - 1];
+    let axes_at = |transform: &Perspective, u: f32, v: f32| {
+        let origin = transform.map(u, v);
+        let (across, down) = (transform.map(u + 1.0, v), transform.map(u, v + 1.0));
+        [
+            (across.0 - origin.0, across.1 - origin.1),
+            (down.0 - origin.0, down.1 - origin.1),
+        ]
+    };
+
+    if version < 7 {
+        // move the fourth point to the bottom right (and only) alignment pattern.
+        // the parallelogram can be way off, so widen the search until it's found
+        let last = indices[1] as f32 + 0.5;
+        let prediction = affine.map(last, last);
+        let bottom_right = [5.0, 10.0, 15.0].into_iter().find_map(|reach| {
+            find_alignment_pattern(image, prediction, axes_at(&affine, last, last), reach)
+        });
+        let global = match bottom_right {
+            Some(found) => {
+                points[3] = found;
+                Perspective::from_points([corners[0], corners[1], corners[2], (last, last)], points)
+                    .unwrap_or(affine)
+            }
+            None => affine,
+        };
+        return Box::new(move |u, v| global.map(u, v));
+    }
+
+    // the alignment patterns form a grid of reference points, along with the
+    // finder patterns in the three corners that don't have one
+    let n = indices.len();
+    let patterns = tables::alignment_pattern_coords(version);
+    let mut modules = Vec::with_capacity(n * n);
+    for &v in indices {
+        for &u in indices {
+            modules.push((u as f32 + 0.5, v as f32 + 0.5));
+        }
+    }
+    let mut references = vec![(0f32, 0f32); n * n];
+    for (index, corner, point) in [(0, 0, 0), (n - 1, 1, 1), ((n - 1) * n, 2, 2)] {
+        modules[index] = corners[corner];
+        references[index] = points[point];
+    }
+
+    // locate the alignment patterns one diagonal at a time, starting from the top
+    // left. the parallelogram is good enough to find the nearest ones, and after
+    // every diagonal, the ones found so far give a better transform to predict
+    // the rest with. the ones that can't be found get another try at the end
+    let mut global = affine;
+    let (mut known_modules, mut known_points) = (corners.to_vec(), points[..3].to_vec());
+    let mut missing = Vec::new();
+    for diagonal in 0..(2 * n - 1) {
+        for i in diagonal.saturating_sub(n - 1)..=diagonal.min(n - 1) {
+            let j = diagonal - i;
+            if !patterns.contains(&(indices[i], indices[j])) {
+                continue;
+            }
+            let (u, v) = modules[j * n + i];
+            let prediction = global.map(u, v);
+            match find_alignment_pattern(image, prediction, axes_at(&global, u, v), 4.0) {
+                Some(found) => {
+                    known_modules.push((u, v));
+                    known_points.push(found);
+                    references[j * n + i] = found;
+                }
+                None => missing.push(j * n + i),
+            }
+        }
+        global = Perspective::fit(&known_modules, &known_points).unwrap_or(global);
+    }
+    for index in missing {
+        let (u, v) = modules[index];
+        let prediction = global.map(u, v);
+        references[index] = find_alignment_pattern(image, prediction, axes_at(&global, u, v), 4.0)
+            .unwrap_or(prediction);
+    }
+
+    // a transform for every cell between four reference points
+    let mut cells = Vec::with_capacity((n - 1) * (n - 1));
+    for j in 0..(n - 1) {
+        for i in 0..(n - 1) {
+            let [a, b, c, d] = [
+                j * n + i,
+                j * n + i + 1,
+                (j + 1) * n + i,
+                (j + 1) * n + i + 1,
+            ];
+            let cell = Perspective::from_points(
+                [modules[a], modules[b], modules[c], modules[d]],
+                [references[a], references[b], references[c], references[d]],
+            );
+            cells.push(cell.unwrap_or(global));
+        }
+    }
+
+    // modules outside the grid of reference points use the nearest cell
+    let cell_index = move |coordinate: f32| {
+        indices[1..n - 1]
+            .iter()
+            .take_while(|&&index| index as f32 + 0.5 <= coordinate)
+            .count()
+    };
+    Box::new(move |u, v| cells[cell_index(v) * (n - 1) + cell_index(u)].map(u, v))
+}
+
+// draws a code with heavy perspective distortion, and reads it back
+#[test]
+fn sample_skewed() -> Result<(), String> {
+    use crate::qr_standard::badstream::{make_qr, QRInput};
+
+    // with these masks, the data has finder and alignment pattern lookalikes
+    // that can be picked over the real ones
+    for (version, mask) in [(3, 5), (12, 5), (25, 5), (3, 0), (12, 6), (24, 3), (26, 2)] {
+        let bitmap = make_qr(
+            QRInput::Auto("perspective".to_string()),
+            Some(version),
+            Some(0),
+            Some(mask),
+        );
+        let size = bitmap.dims().0 as f32;
+
+        // from image pixels to modules
+        let inverse = Perspective::from_points(
+            [(120.0, 80.0), (690.0, 150.0), (70.0, 700.0), (640.0, 620.0)],
+            [(0.0, 0.0), (size, 0.0), (0.0, size), (size, size)],
+        )
+        .unwrap();
+        let mut image = image::Bitmap::new(800, 800);
+        for y in 0..800 {
+            for x in 0..800 {
+                let (u, v) = inverse.map(x as f32 + 0.5, y as f32 + 0.5);
+                if u >= 0.0 && v >= 0.0 && bitmap.get_bit(u as usize, v as usize) == Some(true) {
+                    image.set_bit(x, y, true);
+                }
+            }
+        }
+
+        let decoded =
+            super::read_qr_from_image(&image).map_err(|e| format!("version {}: {}", version, e))?;
+        if decoded.text() != "perspective" {
+            return Err(format!("version {}: read {:?}", version, decoded.text()));
+        }
+    }
+
+    // degenerate fits shouldn't overflow or search forever
+    let image = image::Bitmap::new(50, 50);
+    for (center, axis) in [
+        ((25.0, 25.0), (f32::NAN, 0.0)),
+        ((25.0, 25.0), (f32::INFINITY, 0.0)),
+        ((25.0, 25.0), (1e30, 0.0)),
+        ((f32::MAX, 25.0), (1.0, 0.0)),
+    ] {
+        if find_alignment_pattern(&image, center, [axis; 2], 4.0).is_some() {
+            return Err(format!("found a pattern around {:?}", center));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,531 @@
+use super::{
+    badstream::{self, Badstream, QRInput},
+    bitstream::{self, search, Mode, Token},
+    image,
+};
+use crate::rdsm::{galois, poly};
+
+/*
+micro qr codes are a smaller cousin of regular qr codes, made for things
+like parts marking where a full-size code won't fit:
+1. there's a single finder pattern in the top left, and the timing
+   patterns run along the top and left edges instead of row/column 6
+2. there are four versions, M1 to M4, 11 to 17 modules wide
+3. mode and count indicators are shorter, and the smaller versions
+   can't hold every mode
+4. everything is in a single block, and the data of M1 and M3 ends
+   with a 4-bit codeword
+5. there are only four masks, and the best one is the one with the
+   most dark modules along the right and bottom edges
+*/
+
+/// The symbols of Micro QR, in order of their symbol numbers:
+/// version, error correction level (L, M or Q), number of data bits,
+/// and number of error correction codewords.
+///
+/// M1 only has error detection; it's listed as level L.
+const SYMBOLS: [(u32, u8, usize, usize); 8] = [
+    (1, 0, 20, 2),
+    (2, 0, 40, 5),
+    (2, 1, 32, 6),
+    (3, 0, 84, 6),
+    (3, 1, 68, 8),
+    (4, 0, 128, 8),
+    (4, 1, 112, 10),
+    (4, 2, 80, 14),
+];
+
+/// Character count indicator widths for each version, for the
+/// numeric, alphanumeric, ASCII and kanji modes respectively.
+/// 0 means the mode isn't available.
+const CC_INDICATOR_BITS: [[usize; 4]; 4] = [[3, 0, 0, 0], [4, 3, 0, 0], [5, 4, 4, 3], [6, 5, 5, 4]];
+
+/// The regular QR masks that the four Micro QR masks correspond to.
+const MASKS: [u8; 4] = [1, 4, 6, 7];
+
+/// Make a Micro QR code.
+///
+/// Without a version, the smallest one that fits the message is used.
+/// Without an error correction level, the strongest one available in
+/// that version is used, so a message that fits in M1 (which only has
+/// error detection) gets an M1 code. Level Q only exists in M4, and
+/// there is no level H.
+pub fn make_micro_qr(
+    input: QRInput,
+    version_choice: Option<u32>,
+    level_choice: Option<u8>,
+    mask_choice: Option<u8>,
+) -> Result<image::Bitmap, String> {
+    if version_choice.is_some_and(|v| !(1..=4).contains(&v)) {
+        return Err("Micro QR versions go from M1 to M4".to_string());
+    }
+    if level_choice.is_some_and(|l| l > 2) {
+        return Err("Micro QR codes have no level H".to_string());
+    }
+    if mask_choice.is_some_and(|m| m > 3) {
+        return Err("Micro QR masks go from 0 to 3".to_string());
+    }
+
+    // automatic input is optimized for every version separately,
+    // since they have different indicators and modes
+    let (text, manual) = match input {
+        QRInput::Auto(str) => (Some(str), vec![]),
+        QRInput::Manual(vec) => (None, vec),
+        QRInput::Fnc1(..) => return Err("Micro QR codes have no FNC1 modes".to_string()),
+    };
+
+    // smallest version first, then strongest level
+    let mut order: Vec<usize> = (0..SYMBOLS.len()).collect();
+    order.sort_by_key(|&s| (SYMBOLS[s].0, std::cmp::Reverse(SYMBOLS[s].1)));
+
+    let mut error = "no Micro QR code has that version and level".to_string();
+    for symbol in order {
+        let (version, level, ..) = SYMBOLS[symbol];
+        if version_choice.is_some_and(|v| v != version)
+            || level_choice.is_some_and(|l| l != level || version == 1)
+        {
+            continue;
+        }
+
+        let segments = match &text {
+            Some(str) => search::optimize_mode(str, segment_costs(version)),
+            None => manual.clone(),
+        };
+        // there are no eci markers to say that text is utf-8
+        // (and kanji that the version has no kanji mode for end up as bytes)
+        if segments
+            .iter()
+            .any(|(m, s)| *m == Mode::ASCII && !s.is_ascii())
+        {
+            error = "Micro QR codes can only hold ASCII text and kanji".to_string();
+            continue;
+        }
+        let tokens = bitstream::make_token_stream(segments, None, None);
+        match encode_symbol(&tokens, symbol) {
+            Ok(stream) => return Ok(stream_to_symbol(&stream, symbol, mask_choice)),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// What segments cost in a version of micro qr, for [search::optimize_mode].
+pub fn segment_costs(version: u32) -> search::SegmentCosts {
+    search::SegmentCosts {
+        // the mode indicator is 0 to 3 bits long, for M1 to M4
+        mode_bits: version as usize - 1,
+        count_bits: CC_INDICATOR_BITS[version as usize - 1],
+        fnc1: false,
+    }
+}
+
+/// return `w - 1`, where `w` is the width of a micro qr version.
+fn version_to_max_index(version: u32) -> usize {
+    8 + 2 * version as usize
+}
+
+/// returns the type of pixel taken up by a coordinate in a micro qr code.
+///
+/// from 0 to 3: data, position (and separator), timing, format
+pub fn coord_status(x: usize, y: usize, version: u32) -> Option<u8> {
+    if x.max(y) > version_to_max_index(version) {
+        return None;
+    }
+
+    Some(if x < 8 && y < 8 {
+        1
+    } else if x == 0 || y == 0 {
+        2
+    } else if x <= 8 && y <= 8 {
+        3
+    } else {
+        0
+    })
+}
+
+#[inline]
+pub fn coord_is_data(x: usize, y: usize, version: u32) -> bool {
+    coord_status(x, y, version).is_some_and(|c| c == 0)
+}
+
+/// the same zigzag as in a regular qr code, but without a timing
+/// pattern to skip over: column 0 has no data, so the walk just runs out.
+pub fn next_data_bit(x: usize, y: usize, version: u32) -> Option<(usize, usize)> {
+    if !coord_is_data(x, y, version) {
+        return None;
+    }
+
+    let max = version_to_max_index(version);
+    super::zigzag(x, y, (max, max), None, |x, y| coord_is_data(x, y, version))
+}
+
+fn new_blank_micro_qr(version: u32) -> image::Bitmap {
+    let max = version_to_max_index(version);
+    let mut output = image::Bitmap::new(max + 1, max + 1);
+    let mut set = |x, y| output.set_bit(x, y, true);
+
+    // draw timing patterns
+    for i in (8..=max).step_by(2) {
+        set(i, 0);
+        set(0, i);
+    }
+
+    // draw the position pattern
+    for x in 0..=6usize {
+        for y in 0..=6usize {
+            if (x.abs_diff(3) == 2 && y.abs_diff(3) != 3)
+                || (y.abs_diff(3) == 2 && x.abs_diff(3) != 3)
+            {
+                continue;
+            }
+            set(x, y);
+        }
+    }
+
+    output
+}
+
+// the bits of a micro qr code's data, before error correction
+fn tokens_to_badstream(tokens: &[Token], version: u32) -> Result<Badstream, String> {
+    let mut output: Badstream = Vec::new();
+    let push = |value: u16, width: usize, stream: &mut Badstream| {
+        for k in (0..width).rev() {
+            stream.push(u8::from(value & (1 << k) != 0));
+        }
+    };
+
+    for token in tokens {
+        match *token {
+            Token::ModeAndCount(mode, count) => {
+                let index = match mode {
+                    Mode::Numeric => 0,
+                    Mode::AlphaNum => 1,
+                    Mode::ASCII => 2,
+                    Mode::Kanji => 3,
+                };
+                let width = CC_INDICATOR_BITS[version as usize - 1][index];
+                if width == 0 {
+                    return Err(format!("M{} codes can't hold {:?} data", version, mode));
+                }
+                if count >> width != 0 {
+                    return Err(format!(
+                        "M{} codes can't hold {} characters of {:?} data in one segment",
+                        version, count, mode
+                    ));
+                }
+                // the mode indicator is 0 to 3 bits long, for M1 to M4
+                push(index as u16, version as usize - 1, &mut output);
+                push(count, width, &mut output);
+            }
+            Token::Character(width, value) => push(value, width, &mut output),
+            // micro qr has its own terminator
+            Token::Terminator => {}
+            _ => {
+                return Err(
+                    "Micro QR codes can't hold ECI, FNC1 or Structured Append markers".to_string(),
+                )
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// The full stream of bits in a Micro QR symbol, error correction included.
+fn encode_symbol(tokens: &[Token], symbol: usize) -> Result<Badstream, String> {
+    let (version, level, data_bits, ec_codewords) = SYMBOLS[symbol];
+    let mut stream = tokens_to_badstream(tokens, version)?;
+    if stream.len() > data_bits {
+        return Err(format!(
+            "M{}{} codes only fit {} bits, but the message takes {}",
+            version,
+            if version == 1 {
+                String::new()
+            } else {
+                format!("-{}", b"LMQ"[level as usize] as char)
+            },
+            data_bits,
+            stream.len()
+        ));
+    }
+
+    /*
+    1. terminator: 3, 5, 7 or 9 zeros, or fewer if the symbol is full
+    2. zeros up to the end of the codeword
+    3. alternating pad codewords
+    4. the last codeword of M1 and M3 is only 4 bits, and is left as zeros
+    */
+    let terminator = (2 * version as usize + 1).min(data_bits - stream.len());
+    stream.resize(stream.len() + terminator, 0);
+    stream.resize(stream.len().next_multiple_of(8).min(data_bits), 0);
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if stream.len() + 8 > data_bits {
+            break;
+        }
+        badstream::push_byte(pad, &mut stream);
+    }
+    stream.resize(data_bits, 0);
+
+    // for error correction, a 4-bit codeword is the top half of a byte
+    let mut padded = stream.clone();
+    padded.resize(data_bits.next_multiple_of(8), 0);
+    let encoded = poly::encode_message(
+        &badstream::badstream_to_polynomial(&padded),
+        ec_codewords as u32,
+    );
+    for &codeword in &encoded[data_bits.div_ceil(8)..] {
+        badstream::push_byte(codeword, &mut stream);
+    }
+
+    Ok(stream)
+}
+
+fn stream_to_symbol(stream: &Badstream, symbol: usize, mask_choice: Option<u8>) -> image::Bitmap {
+    let version = SYMBOLS[symbol].0;
+    let max = version_to_max_index(version);
+    let mut bitmap = new_blank_micro_qr(version);
+
+    let (mut x, mut y) = (max, max);
+    for (a, &i) in stream.iter().enumerate() {
+        bitmap.set_bit(x, y, i != 0);
+        match next_data_bit(x, y, version) {
+            Some((x2, y2)) => (x, y) = (x2, y2),
+            None => {
+                assert!(
+                    a + 1 == stream.len(),
+                    "stream_to_symbol(): bitstream is {} bits but image fits {}",
+                    stream.len(),
+                    a + 1,
+                );
+                break;
+            }
+        }
+    }
+
+    let masks = match mask_choice {
+        Some(mask) => mask..=mask,
+        None => 0..=3,
+    };
+    masks
+        .map(|mask| {
+            let mut clone = bitmap.clone();
+            apply_mask(&mut clone, symbol, mask);
+            clone
+        })
+        // the highest score wins, and the lowest mask on a tie
+        .min_by_key(|clone| std::cmp::Reverse(mask_score(clone, max)))
+        .unwrap()
+}
+
+fn apply_mask(bitmap: &mut image::Bitmap, symbol: usize, mask: u8) {
+    let version = SYMBOLS[symbol].0;
+    let max = version_to_max_index(version);
+    for x in 0..=max {
+        for y in 0..=max {
+            if coord_is_data(x, y, version) && super::mask_bit(MASKS[mask as usize], x, y) {
+                bitmap.set_bit(x, y, !bitmap.get_bit(x, y).unwrap());
+            }
+        }
+    }
+    set_fcode(bitmap, symbol, mask);
+}
+
+/// coordinates of the format information, from LSB (0) to MSB (14).
+///
+/// unlike in a regular qr code, there's only one copy of it.
+fn format_info_coords(bit: u32) -> (usize, usize) {
+    let bit = bit as usize;
+    match bit {
+        0..=7 => (8, bit + 1),
+        _ => (15 - bit, 8),
+    }
+}
+
+fn set_fcode(bitmap: &mut image::Bitmap, symbol: usize, mask: u8) {
+    let fcode = galois::qr_generate_fcode(((symbol as u8) << 2) | mask)
+        .expect("could not generate format code");
+    let mask = 0b0100_0100_0100_0101u16;
+
+    for bit in 0..=14 {
+        let (x, y) = format_info_coords(bit);
+        bitmap.set_bit(x, y, (fcode ^ mask) & (1 << bit) != 0);
+    }
+}
+
+// the higher the better: the number of dark modules along the right
+// and bottom edges, with the edge that has fewer counting most
+fn mask_score(bitmap: &image::Bitmap, max: usize) -> usize {
+    let dark = |x, y| bitmap.get_bit(x, y) == Some(true);
+    let right = (1..=max).filter(|&y| dark(max, y)).count();
+    let bottom = (1..=max).filter(|&x| dark(x, max)).count();
+    16 * right.min(bottom) + right.max(bottom)
+}
+
+#[test]
+fn micro_symbols() -> Result<(), String> {
+    for (text, symbol) in [
+        ("12345", 0),
+        ("HELLO", 2),
+        ("Hello", 4),
+        ("Hello, world!", 6),
+        ("漢字", 4),
+        // only fit when mode switches are costed with micro qr's indicators
+        ("4V882930", 1),
+        ("9N3Ac1DK78", 3),
+    ] {
+        let bitmap = make_micro_qr(QRInput::Auto(text.to_string()), None, None, None)?;
+        let version = SYMBOLS[symbol].0;
+        let max = version_to_max_index(version);
+        if bitmap.dims() != (max + 1, max + 1) {
+            return Err(format!("{text}: wrong size {:?}", bitmap.dims()));
+        }
+
+        // timing patterns
+        for i in 8..=max {
+            if bitmap.get_bit(i, 0) != Some(i % 2 == 0) || bitmap.get_bit(0, i) != Some(i % 2 == 0)
+            {
+                return Err(format!("{text}: broken timing pattern"));
+            }
+        }
+
+        // format information
+        let mut fcode = 0;
+        for bit in 0..=14 {
+            let (x, y) = format_info_coords(bit);
+            fcode |= u16::from(bitmap.get_bit(x, y).unwrap()) << bit;
+        }
+        let (fmt, errors) = galois::qr_correct_fcode(fcode ^ 0x4445).ok_or("bad format code")?;
+        if errors != 0 || fmt >> 2 != symbol as u8 {
+            return Err(format!("{text}: format information {fmt:05b}"));
+        }
+
+        // the data, read back along the zigzag with the mask removed
+        let mut unmasked = bitmap.clone();
+        apply_mask(&mut unmasked, symbol, fmt & 3);
+        let (mut x, mut y) = (max, max);
+        let mut stream: Badstream = vec![u8::from(unmasked.get_bit(x, y).unwrap())];
+        while let Some((x2, y2)) = next_data_bit(x, y, version) {
+            (x, y) = (x2, y2);
+            stream.push(u8::from(unmasked.get_bit(x, y).unwrap()));
+        }
+        let tokens = bitstream::make_token_stream(
+            search::optimize_mode(&text.into(), segment_costs(version)),
+            None,
+            None,
+        );
+        let expected = encode_symbol(&tokens, symbol)?;
+        if !stream
+            .into_iter()
+            .eq(expected.into_iter().map(|b| u8::from(b != 0)))
+        {
+            return Err(format!("{text}: data doesn't read back"));
+        }
+    }
+
+    // levels and versions that don't exist, and data that doesn't fit
+    let input = || QRInput::Auto("12345".to_string());
+    if make_micro_qr(input(), None, Some(3), None).is_ok()
+        || make_micro_qr(input(), Some(2), Some(2), None).is_ok()
+        || make_micro_qr(input(), Some(5), None, None).is_ok()
+        || make_micro_qr(QRInput::Auto("hello".into()), Some(2), None, None).is_ok()
+    {
+        return Err("invalid micro qr code was made".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn micro_reference_symbols() -> Result<(), String> {
+    // the M2-L example from ISO/IEC 18004: five data codewords and five
+    // error correction codewords
+    let tokens = bitstream::make_token_stream(
+        search::optimize_mode(&"01234567".into(), segment_costs(2)),
+        None,
+        None,
+    );
+    let codewords = badstream::badstream_to_polynomial(&encode_symbol(&tokens, 1)?);
+    let expected = [0x40, 0x18, 0xAC, 0xC3, 0x00, 0x86, 0x0D, 0x22, 0xAE, 0x30];
+    if !codewords.iter().eq(expected.iter()) {
+        return Err(format!("01234567: codewords {:02X?}", codewords));
+    }
+
+    // whole symbols, as drawn by the qrcode crate (which picks the same masks)
+    for (text, version, level, grid) in [
+        (
+            "01234567",
+            2,
+            0,
+            "
+            #######.#.#.#
+            #.....#.###.#
+            #.###.#..##.#
+            #.###.#..####
+            #.###.#.###..
+            #.....#.#...#
+            #######..####
+            .........##..
+            ##.#....#...#
+            .##.#.#.#.#.#
+            ###..#######.
+            ...#.#....##.
+            ###.#..##.###
+            ",
+        ),
+        (
+            "HELLO",
+            3,
+            1,
+            "
+            #######.#.#.#.#
+            #.....#...##..#
+            #.###.#.#.##..#
+            #.###.#.###....
+            #.###.#.#####..
+            #.....#.###..##
+            #######..#.###.
+            ........#.#..#.
+            #....##.#.###.#
+            .#####.#.......
+            ##..#...######.
+            .#..##...#.###.
+            #.####.#####..#
+            ..#.#.##.#.###.
+            ###.#.#..####.#
+            ",
+        ),
+        (
+            "Hello",
+            4,
+            0,
+            "
+            #######.#.#.#.#.#
+            #.....#.####.#...
+            #.###.#..#.###...
+            #.###.#.####.##.#
+            #.###.#..######.#
+            #.....#.#....#.#.
+            #######.#.#..#..#
+            ............###.#
+            #..#.###..#.....#
+            .#####...###.....
+            ##.#.#.#.#.#####.
+            .##..#.#.###.....
+            #..#.#...######.#
+            .#.#...........#.
+            ##.##..#..#..##.#
+            ...#.#.#....#....
+            #.###.##..#..##.#
+            ",
+        ),
+    ] {
+        let bitmap = make_micro_qr(
+            QRInput::Auto(text.to_string()),
+            Some(version),
+            Some(level),
+            None,
+        )?;
+        if bitmap.as_text() != image::Bitmap::from_text(grid)?.as_text() {
+            return Err(format!("{text}: symbol differs:\n{}", bitmap.as_text()));
+        }
+    }
+    Ok(())
+}
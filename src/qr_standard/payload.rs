@@ -0,0 +1,775 @@
+/*
+a lot of QR codes hold more than plain text: links, wi-fi logins, contact
+cards and so on. none of these formats are part of the QR standard itself,
+they're conventions that phone apps have settled on, so parsing is lenient:
+anything that doesn't fit a format is just text
+*/
+
+/// The contents of a code, recognized as one of the common formats.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload {
+    /// Anything that isn't in one of the other formats.
+    Text(String),
+    /// A web address.
+    Url(String),
+    /// Login details for a wi-fi network (`WIFI:`).
+    Wifi(Wifi),
+    /// A contact card (`MECARD:` or vCard).
+    Contact(Contact),
+    /// An email to be written (`mailto:` or `MATMSG:`).
+    Email {
+        to: String,
+        subject: Option<String>,
+        body: Option<String>,
+    },
+    /// A phone number to call (`tel:`).
+    Phone(String),
+    /// A text message to be written (`sms:` or `SMSTO:`).
+    Sms {
+        number: String,
+        message: Option<String>,
+    },
+    /// A place on Earth (`geo:`), in degrees and meters.
+    Geo {
+        latitude: f64,
+        longitude: f64,
+        altitude: Option<f64>,
+        query: Option<String>,
+    },
+    /// A key for an authenticator app (`otpauth://`).
+    OneTimePassword(OneTimePassword),
+    /// A SEPA credit transfer (an EPC code, also known as GiroCode).
+    Payment(Payment),
+    /// A calendar event (iCalendar `VEVENT`).
+    Event(Event),
+}
+
+/// Login details for a wi-fi network.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Wifi {
+    pub ssid: String,
+    /// The kind of authentication, such as `WPA` or `WEP`, or `None` for an
+    /// open network.
+    pub security: Option<String>,
+    pub password: Option<String>,
+    /// Whether the network hides its name.
+    pub hidden: bool,
+}
+
+/// A contact card.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Contact {
+    pub name: Option<String>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub urls: Vec<String>,
+    pub address: Option<String>,
+    pub birthday: Option<String>,
+    pub note: Option<String>,
+}
+
+/// A key for a time-based (TOTP) or counter-based (HOTP) one-time password.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OneTimePassword {
+    /// `totp` or `hotp`.
+    pub kind: String,
+    pub issuer: Option<String>,
+    pub account: String,
+    /// The shared secret, in base 32.
+    pub secret: String,
+    /// The hash function, `SHA1` unless given.
+    pub algorithm: String,
+    /// The length of the passwords, 6 unless given.
+    pub digits: u32,
+    /// For TOTP, how many seconds every password lasts, 30 unless given.
+    pub period: Option<u32>,
+    /// For HOTP, the initial counter value.
+    pub counter: Option<u64>,
+}
+
+/// A SEPA credit transfer, as described in EPC069-12.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Payment {
+    pub bic: Option<String>,
+    pub name: String,
+    pub iban: String,
+    /// The amount as written in the code, without the currency.
+    pub amount: Option<String>,
+    pub currency: Option<String>,
+    pub purpose: Option<String>,
+    /// A structured creditor reference.
+    pub reference: Option<String>,
+    /// A free-form message to the recipient.
+    pub text: Option<String>,
+    /// A note to the payer.
+    pub information: Option<String>,
+}
+
+/// A calendar event.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    pub summary: Option<String>,
+    /// The start and end times, as written in the code (e.g. `20260101T090000Z`).
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Recognize the format of the contents of a code.
+pub fn parse(text: &str) -> Payload {
+    let trimmed = text.trim();
+    let parsed = if let Some(rest) = strip_prefix(trimmed, "WIFI:") {
+        parse_wifi(rest)
+    } else if let Some(rest) = strip_prefix(trimmed, "MECARD:") {
+        parse_mecard(rest)
+    } else if strip_prefix(trimmed, "BEGIN:VCARD").is_some() {
+        parse_vcard(trimmed)
+    } else if strip_prefix(trimmed, "BEGIN:VCALENDAR").is_some()
+        || strip_prefix(trimmed, "BEGIN:VEVENT").is_some()
+    {
+        parse_event(trimmed)
+    } else if let Some(rest) = strip_prefix(trimmed, "mailto:") {
+        parse_mailto(rest)
+    } else if let Some(rest) = strip_prefix(trimmed, "MATMSG:") {
+        parse_matmsg(rest)
+    } else if let Some(rest) = strip_prefix(trimmed, "tel:") {
+        Some(Payload::Phone(percent_decode(rest)))
+    } else if let Some(rest) = strip_prefix(trimmed, "SMSTO:") {
+        // SMSTO:number:message
+        let (number, message) = rest.split_once(':').unwrap_or((rest, ""));
+        Some(Payload::Sms {
+            number: number.to_string(),
+            message: non_empty(message.to_string()),
+        })
+    } else if let Some(rest) = strip_prefix(trimmed, "sms:") {
+        let (number, query) = split_query(rest);
+        Some(Payload::Sms {
+            number: percent_decode(number),
+            message: query_value(query, "body"),
+        })
+    } else if let Some(rest) = strip_prefix(trimmed, "geo:") {
+        parse_geo(rest)
+    } else if let Some(rest) = strip_prefix(trimmed, "otpauth://") {
+        parse_otpauth(rest)
+    } else if trimmed.starts_with("BCD") {
+        parse_epc(text)
+    } else if ["http://", "https://", "ftp://"]
+        .iter()
+        .any(|scheme| strip_prefix(trimmed, scheme).is_some())
+        && !trimmed.contains(char::is_whitespace)
+    {
+        Some(Payload::Url(trimmed.to_string()))
+    } else {
+        None
+    };
+    parsed.unwrap_or_else(|| Payload::Text(text.to_string()))
+}
+
+impl std::fmt::Display for Payload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Payload::Text(text) => write!(f, "{}", text),
+            Payload::Url(url) => write!(f, "Link to {}", url),
+            Payload::Wifi(wifi) => {
+                write!(
+                    f,
+                    "Wi-Fi network {} ({}{})",
+                    wifi.ssid,
+                    wifi.security.as_deref().unwrap_or("open"),
+                    if wifi.hidden { ", hidden" } else { "" }
+                )?;
+                if let Some(password) = &wifi.password {
+                    write!(f, "\n    password: {}", password)?;
+                }
+                Ok(())
+            }
+            Payload::Contact(contact) => {
+                write!(f, "Contact {}", contact.name.as_deref().unwrap_or("card"))?;
+                let single = [
+                    ("organization", &contact.organization),
+                    ("title", &contact.title),
+                    ("address", &contact.address),
+                    ("birthday", &contact.birthday),
+                    ("note", &contact.note),
+                ];
+                let multiple = [
+                    ("phone", &contact.phones),
+                    ("email", &contact.emails),
+                    ("url", &contact.urls),
+                ];
+                for (label, values) in multiple {
+                    for value in values {
+                        write!(f, "\n    {}: {}", label, value)?;
+                    }
+                }
+                for (label, value) in single {
+                    if let Some(value) = value {
+                        write!(f, "\n    {}: {}", label, value)?;
+                    }
+                }
+                Ok(())
+            }
+            Payload::Email { to, subject, body } => {
+                write!(f, "Email to {}", to)?;
+                if let Some(subject) = subject {
+                    write!(f, "\n    subject: {}", subject)?;
+                }
+                if let Some(body) = body {
+                    write!(f, "\n    body: {}", body)?;
+                }
+                Ok(())
+            }
+            Payload::Phone(number) => write!(f, "Phone number {}", number),
+            Payload::Sms { number, message } => {
+                write!(f, "Text message to {}", number)?;
+                if let Some(message) = message {
+                    write!(f, "\n    message: {}", message)?;
+                }
+                Ok(())
+            }
+            Payload::Geo {
+                latitude,
+                longitude,
+                altitude,
+                query,
+            } => {
+                write!(f, "Location {}, {}", latitude, longitude)?;
+                if let Some(altitude) = altitude {
+                    write!(f, ", {} m", altitude)?;
+                }
+                if let Some(query) = query {
+                    write!(f, " ({})", query)?;
+                }
+                Ok(())
+            }
+            Payload::OneTimePassword(otp) => {
+                write!(f, "One-time password ({}) for ", otp.kind.to_uppercase())?;
+                match &otp.issuer {
+                    Some(issuer) => write!(f, "{} at {}", otp.account, issuer)?,
+                    None => write!(f, "{}", otp.account)?,
+                }
+                write!(f, "\n    {} digits, {}", otp.digits, otp.algorithm)?;
+                match (otp.period, otp.counter) {
+                    (Some(period), _) => write!(f, ", every {} seconds", period),
+                    (_, Some(counter)) => write!(f, ", counter at {}", counter),
+                    _ => Ok(()),
+                }
+            }
+            Payload::Payment(payment) => {
+                write!(f, "Payment to {}", payment.name)?;
+                if let Some(amount) = &payment.amount {
+                    write!(
+                        f,
+                        " of {} {}",
+                        amount,
+                        payment.currency.as_deref().unwrap_or("EUR")
+                    )?;
+                }
+                write!(f, "\n    IBAN: {}", payment.iban)?;
+                let details = [
+                    ("BIC", &payment.bic),
+                    ("purpose", &payment.purpose),
+                    ("reference", &payment.reference),
+                    ("text", &payment.text),
+                    ("information", &payment.information),
+                ];
+                for (label, value) in details {
+                    if let Some(value) = value {
+                        write!(f, "\n    {}: {}", label, value)?;
+                    }
+                }
+                Ok(())
+            }
+            Payload::Event(event) => {
+                write!(
+                    f,
+                    "Event {}",
+                    event.summary.as_deref().unwrap_or("(untitled)")
+                )?;
+                let details = [
+                    ("start", &event.start),
+                    ("end", &event.end),
+                    ("location", &event.location),
+                    ("description", &event.description),
+                ];
+                for (label, value) in details {
+                    if let Some(value) = value {
+                        write!(f, "\n    {}: {}", label, value)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// WIFI:T:WPA;S:network;P:password;H:true;;
+fn parse_wifi(body: &str) -> Option<Payload> {
+    let mut wifi = Wifi::default();
+    for (key, value) in semicolon_fields(body) {
+        match key.as_str() {
+            "S" => wifi.ssid = value,
+            "T" if !value.eq_ignore_ascii_case("nopass") => wifi.security = non_empty(value),
+            "P" => wifi.password = non_empty(value),
+            "H" => wifi.hidden = value.eq_ignore_ascii_case("true"),
+            _ => {}
+        }
+    }
+    (!wifi.ssid.is_empty()).then_some(Payload::Wifi(wifi))
+}
+
+// MECARD:N:Doe,John;TEL:123;EMAIL:john@example.com;;
+fn parse_mecard(body: &str) -> Option<Payload> {
+    let mut contact = Contact::default();
+    for (key, value) in semicolon_fields(body) {
+        match key.as_str() {
+            // "last,first"
+            "N" => {
+                let name = match value.split_once(',') {
+                    Some((last, first)) => format!("{} {}", first.trim(), last.trim()),
+                    None => value,
+                };
+                contact.name = non_empty(name.trim().to_string());
+            }
+            "ORG" => contact.organization = non_empty(value),
+            "TITLE" => contact.title = non_empty(value),
+            "TEL" => contact.phones.push(value),
+            "EMAIL" => contact.emails.push(value),
+            "URL" => contact.urls.push(value),
+            "ADR" => contact.address = non_empty(value),
+            "BDAY" => contact.birthday = non_empty(value),
+            "NOTE" => contact.note = non_empty(value),
+            _ => {}
+        }
+    }
+    Some(Payload::Contact(contact))
+}
+
+fn parse_vcard(text: &str) -> Option<Payload> {
+    let mut contact = Contact::default();
+    let mut structured_name = None;
+    for (name, raw) in content_lines(text) {
+        let value = unescape(&raw);
+        match name.as_str() {
+            "FN" => contact.name = non_empty(value),
+            // family;given;additional;prefixes;suffixes
+            "N" => {
+                let parts: Vec<_> = raw.split(';').map(unescape).collect();
+                let order = [3, 1, 2, 0, 4];
+                let name: Vec<_> = order
+                    .iter()
+                    .filter_map(|&i| parts.get(i))
+                    .filter(|part| !part.is_empty())
+                    .cloned()
+                    .collect();
+                structured_name = non_empty(name.join(" "));
+            }
+            "ORG" => contact.organization = non_empty(join_components(&raw)),
+            "TITLE" => contact.title = non_empty(value),
+            "TEL" => contact.phones.push(value),
+            "EMAIL" => contact.emails.push(value),
+            "URL" => contact.urls.push(value),
+            "ADR" => contact.address = non_empty(join_components(&raw)),
+            "BDAY" => contact.birthday = non_empty(value),
+            "NOTE" => contact.note = non_empty(value),
+            _ => {}
+        }
+    }
+    contact.name = contact.name.or(structured_name);
+    Some(Payload::Contact(contact))
+}
+
+fn parse_event(text: &str) -> Option<Payload> {
+    let mut event = Event::default();
+    let mut in_event = false;
+    for (name, raw) in content_lines(text) {
+        let value = unescape(&raw);
+        match name.as_str() {
+            "BEGIN" => in_event |= value.eq_ignore_ascii_case("VEVENT"),
+            "END" if value.eq_ignore_ascii_case("VEVENT") => break,
+            _ if !in_event => {}
+            "SUMMARY" => event.summary = non_empty(value),
+            "DTSTART" => event.start = non_empty(value),
+            "DTEND" => event.end = non_empty(value),
+            "LOCATION" => event.location = non_empty(value),
+            "DESCRIPTION" => event.description = non_empty(value),
+            _ => {}
+        }
+    }
+    in_event.then_some(Payload::Event(event))
+}
+
+// mailto:someone@example.com?subject=hi&body=hello
+fn parse_mailto(body: &str) -> Option<Payload> {
+    let (to, query) = split_query(body);
+    Some(Payload::Email {
+        to: percent_decode(to),
+        subject: query_value(query, "subject"),
+        body: query_value(query, "body"),
+    })
+}
+
+// MATMSG:TO:someone@example.com;SUB:hi;BODY:hello;;
+fn parse_matmsg(body: &str) -> Option<Payload> {
+    let (mut to, mut subject, mut text) = (String::new(), None, None);
+    for (key, value) in semicolon_fields(body) {
+        match key.as_str() {
+            "TO" => to = value,
+            "SUB" => subject = non_empty(value),
+            "BODY" => text = non_empty(value),
+            _ => {}
+        }
+    }
+    Some(Payload::Email {
+        to,
+        subject,
+        body: text,
+    })
+}
+
+// geo:latitude,longitude[,altitude][;parameters][?q=query]
+fn parse_geo(body: &str) -> Option<Payload> {
+    let (coordinates, query) = split_query(body);
+    let coordinates = coordinates.split(';').next().unwrap_or_default();
+    let numbers = coordinates
+        .split(',')
+        .map(|n| n.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match numbers[..] {
+        [latitude, longitude] | [latitude, longitude, _] => Some(Payload::Geo {
+            latitude,
+            longitude,
+            altitude: numbers.get(2).copied(),
+            query: query_value(query, "q"),
+        }),
+        _ => None,
+    }
+}
+
+// otpauth://totp/Issuer:account?secret=ABC&issuer=Issuer&digits=6
+fn parse_otpauth(body: &str) -> Option<Payload> {
+    let (path, query) = split_query(body);
+    let (kind, label) = path.split_once('/')?;
+    let kind = kind.to_ascii_lowercase();
+    if kind != "totp" && kind != "hotp" {
+        return None;
+    }
+    let label = percent_decode(label);
+    let (label_issuer, account) = match label.split_once(':') {
+        Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim()),
+        None => (None, label.as_str()),
+    };
+    let number = |key| query_value(query, key).and_then(|value| value.parse().ok());
+    Some(Payload::OneTimePassword(OneTimePassword {
+        issuer: query_value(query, "issuer").or(label_issuer),
+        account: account.to_string(),
+        secret: query_value(query, "secret")?,
+        algorithm: query_value(query, "algorithm").unwrap_or_else(|| "SHA1".to_string()),
+        digits: number("digits").unwrap_or(6),
+        period: (kind == "totp").then(|| number("period").unwrap_or(30)),
+        counter: query_value(query, "counter").and_then(|value| value.parse().ok()),
+        kind,
+    }))
+}
+
+/*
+an EPC code is a fixed list of lines:
+ 0. "BCD"
+ 1. version, "001" or "002"
+ 2. character set, 1 to 8
+ 3. "SCT"
+ 4. BIC (can be left out in version 2)
+ 5. recipient's name
+ 6. IBAN
+ 7. amount, e.g. "EUR12.34" (optional)
+ 8. purpose code (optional)
+ 9. structured reference (optional)
+10. unstructured text (optional)
+11. information for the payer (optional)
+the character set field is ignored, as the bytes have already been
+read as text by the time they get here
+*/
+fn parse_epc(text: &str) -> Option<Payload> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let line = |i: usize| non_empty(lines.get(i).copied().unwrap_or_default().to_string());
+    if lines.len() < 7 || lines[0] != "BCD" || lines[3] != "SCT" {
+        return None;
+    }
+    if lines[1] != "001" && lines[1] != "002" {
+        return None;
+    }
+    let (currency, amount) = match line(7) {
+        Some(amount) if amount.len() > 3 && amount.is_char_boundary(3) => {
+            let (currency, number) = amount.split_at(3);
+            (Some(currency.to_string()), Some(number.to_string()))
+        }
+        _ => (None, None),
+    };
+    Some(Payload::Payment(Payment {
+        bic: line(4),
+        name: line(5)?,
+        iban: line(6)?,
+        amount,
+        currency,
+        purpose: line(8),
+        reference: line(9),
+        text: line(10),
+        information: line(11),
+    }))
+}
+
+// text with the prefix removed, ignoring case
+fn strip_prefix<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &text[prefix.len()..])
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+// the KEY:value; fields of WIFI, MECARD and MATMSG, where \ escapes
+// the next character. keys are uppercased
+fn semicolon_fields(body: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut key = None;
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next()),
+            ':' if key.is_none() => key = Some(std::mem::take(&mut field).to_ascii_uppercase()),
+            ';' => {
+                if let Some(key) = key.take() {
+                    fields.push((key, std::mem::take(&mut field)));
+                }
+                field.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+    if let Some(key) = key {
+        fields.push((key, field));
+    }
+    fields
+}
+
+/*
+the lines of a vCard or iCalendar file, each of the form
+"NAME;PARAMETER=...:value". lines that start with a space continue the
+previous one. names are uppercased, with any "group." prefix and
+parameters removed. values are left escaped, see unescape
+*/
+fn content_lines(text: &str) -> Vec<(String, String)> {
+    let mut unfolded: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), unfolded.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => unfolded.push(line.to_string()),
+        }
+    }
+
+    let mut output = Vec::new();
+    for line in unfolded {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.split(';').next().unwrap_or_default();
+        let name = name.rsplit('.').next().unwrap_or_default();
+        output.push((name.trim().to_ascii_uppercase(), value.to_string()));
+    }
+    output
+}
+
+// a vCard or iCalendar value with its backslash escapes resolved
+fn unescape(value: &str) -> String {
+    let mut output = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => output.push('\n'),
+                Some(escaped) => output.push(escaped),
+                None => {}
+            },
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+// the parts of a structured vCard value like an address, split at the
+// unescaped semicolons and joined with commas, leaving out empty ones
+fn join_components(value: &str) -> String {
+    let mut parts = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        match c {
+            '\\' => {
+                part.push(c);
+                part.extend(chars.next());
+            }
+            ';' => parts.push(String::new()),
+            _ => part.push(c),
+        }
+    }
+    let parts: Vec<_> = parts
+        .iter()
+        .map(|part| unescape(part).trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect();
+    parts.join(", ")
+}
+
+fn split_query(uri: &str) -> (&str, &str) {
+    uri.split_once('?').unwrap_or((uri, ""))
+}
+
+// the first value of a key in a URI query string, decoded
+fn query_value(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .and_then(|(_, value)| non_empty(percent_decode(value)))
+}
+
+// "%20" to " " and so on. invalid escapes are left as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                output.push(byte);
+                i += 3;
+            }
+            None => {
+                output.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+#[test]
+fn parse_payloads() -> Result<(), String> {
+    let samples = [
+        (
+            "WIFI:T:WPA;S:my\\;network;P:hunter2;H:true;;",
+            Payload::Wifi(Wifi {
+                ssid: "my;network".to_string(),
+                security: Some("WPA".to_string()),
+                password: Some("hunter2".to_string()),
+                hidden: true,
+            }),
+        ),
+        (
+            "https://example.com/?a=b",
+            Payload::Url("https://example.com/?a=b".to_string()),
+        ),
+        (
+            "MECARD:N:Doe,John;TEL:+15551234;EMAIL:john@example.com;;",
+            Payload::Contact(Contact {
+                name: Some("John Doe".to_string()),
+                phones: vec!["+15551234".to_string()],
+                emails: vec!["john@example.com".to_string()],
+                ..Default::default()
+            }),
+        ),
+        (
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane;;;\r\nORG:Acme\\, Inc.;Research\r\n\
+            item1.TEL;TYPE=CELL:+15550000\r\nADR:;;1 Main St;Springfield;;12345;\r\n \
+            USA\r\nEND:VCARD",
+            Payload::Contact(Contact {
+                name: Some("Jane Doe".to_string()),
+                organization: Some("Acme, Inc., Research".to_string()),
+                phones: vec!["+15550000".to_string()],
+                address: Some("1 Main St, Springfield, 12345, USA".to_string()),
+                ..Default::default()
+            }),
+        ),
+        (
+            "mailto:a@example.com?subject=Hello%20there&body=Hi",
+            Payload::Email {
+                to: "a@example.com".to_string(),
+                subject: Some("Hello there".to_string()),
+                body: Some("Hi".to_string()),
+            },
+        ),
+        ("tel:+4412345", Payload::Phone("+4412345".to_string())),
+        (
+            "SMSTO:12345:on my way",
+            Payload::Sms {
+                number: "12345".to_string(),
+                message: Some("on my way".to_string()),
+            },
+        ),
+        (
+            "geo:59.3293,18.0686?q=Stockholm",
+            Payload::Geo {
+                latitude: 59.3293,
+                longitude: 18.0686,
+                altitude: None,
+                query: Some("Stockholm".to_string()),
+            },
+        ),
+        (
+            "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&digits=8",
+            Payload::OneTimePassword(OneTimePassword {
+                kind: "totp".to_string(),
+                issuer: Some("Example".to_string()),
+                account: "alice@example.com".to_string(),
+                secret: "JBSWY3DPEHPK3PXP".to_string(),
+                algorithm: "SHA1".to_string(),
+                digits: 8,
+                period: Some(30),
+                counter: None,
+            }),
+        ),
+        (
+            "BCD\n002\n1\nSCT\n\nRed Cross\nBE72000000001616\nEUR12.5\n\n\nDonation",
+            Payload::Payment(Payment {
+                name: "Red Cross".to_string(),
+                iban: "BE72000000001616".to_string(),
+                amount: Some("12.5".to_string()),
+                currency: Some("EUR".to_string()),
+                text: Some("Donation".to_string()),
+                ..Default::default()
+            }),
+        ),
+        (
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Launch\nDTSTART:20261018T090000Z\n\
+            LOCATION:Room 1\\, floor 2\nEND:VEVENT\nEND:VCALENDAR",
+            Payload::Event(Event {
+                summary: Some("Launch".to_string()),
+                start: Some("20261018T090000Z".to_string()),
+                location: Some("Room 1, floor 2".to_string()),
+                ..Default::default()
+            }),
+        ),
+        // not quite any format
+        ("geo:north", Payload::Text("geo:north".to_string())),
+        ("Hello, world!", Payload::Text("Hello, world!".to_string())),
+    ];
+
+    for (text, expected) in samples {
+        let parsed = parse(text);
+        if parsed != expected {
+            return Err(format!("{:?}: got {:?}", text, parsed));
+        }
+    }
+    Ok(())
+}
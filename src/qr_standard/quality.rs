@@ -0,0 +1,355 @@
+use super::{decode, detect, image, tables};
+
+/*
+print quality grading, following iso/iec 15415 for 2d codes in general and
+the qr-specific parts of iso/iec 18004. a few simplifications:
+- reflectance is taken straight from the pixel values, with no calibration
+- every module is measured with a round aperture 0.8 modules wide
+- the misdecode protection codewords of the smallest versions are ignored
+  when counting unused error correction
+*/
+
+/// A print quality grade, from A (best) to F (failing).
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Grade {
+    F,
+    D,
+    C,
+    B,
+    A,
+}
+
+impl Grade {
+    /// The grade as a number, from 0 (F) to 4 (A).
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    // the grade of a measurement that's better the higher it is,
+    // given the lowest values that get an A, B, C and D
+    fn at_least(value: f32, limits: [f32; 4]) -> Grade {
+        [Grade::A, Grade::B, Grade::C, Grade::D]
+            .into_iter()
+            .zip(limits)
+            .find(|&(_, limit)| value >= limit)
+            .map_or(Grade::F, |(grade, _)| grade)
+    }
+
+    // the same for a measurement that's better the lower it is
+    fn at_most(value: f32, limits: [f32; 4]) -> Grade {
+        Grade::at_least(-value, limits.map(|limit| -limit))
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The print quality of a QR code, as graded by [grade].
+///
+/// Reflectances and contrasts are given from 0 to 1, and distances in modules.
+#[derive(Clone, Debug)]
+pub struct QualityReport {
+    /// A if the code could be decoded, F otherwise.
+    pub decode: Grade,
+    /// The difference between the lightest and darkest reflectance.
+    pub symbol_contrast: f32,
+    pub symbol_contrast_grade: Grade,
+    /// How clearly the modules stand out from the threshold between light
+    /// and dark, weighed against how much error correction is left over.
+    pub modulation_grade: Grade,
+    /// How many modules of the finder and timing patterns are wrong.
+    pub fixed_pattern_damage_grade: Grade,
+    /// How much the module width differs from the module height.
+    pub axial_nonuniformity: f32,
+    pub axial_nonuniformity_grade: Grade,
+    /// How far the furthest module is from an evenly spaced grid.
+    pub grid_nonuniformity: f32,
+    pub grid_nonuniformity_grade: Grade,
+    /// The share of error correction capacity left over in the worst block.
+    pub unused_error_correction: f32,
+    pub unused_error_correction_grade: Grade,
+    /// The lowest of all the grades.
+    pub overall: Grade,
+}
+
+/// Grade the print quality of the QR code in a grayscale image.
+///
+/// Returns an error if no code can be found at all; a code that can be found
+/// but not decoded still gets a report, with a failing grade.
+pub fn grade(image: &image::GrayImage) -> Result<QualityReport, String> {
+    let bitmap = image.binarize();
+    let patterns = detect::find_finder_patterns(&bitmap);
+    let finders = detect::locate_symbol(&patterns).ok_or("couldn't find a QR code")?;
+    let dimension = detect::symbol_dimension(&bitmap, &finders);
+    let version = (dimension as u32 - 17) / 4;
+    let transform = detect::perspective::grid_transform(&bitmap, &finders, dimension);
+
+    // the average reflectance around the center of a module, if it's in the image
+    let reflectance = |u: f32, v: f32| {
+        let (x, y) = transform(u, v);
+        let ((ax, ay), (bx, by)) = (transform(u + 1.0, v), transform(u, v + 1.0));
+        let radius = 0.4 * ((ax - x).hypot(ay - y) + (bx - x).hypot(by - y)) / 2.0;
+
+        // a degenerate transform can put a module anywhere, at any size
+        let (width, height) = image.dims();
+        let near = |c: f32, max: usize| (-radius..=max as f32 + radius).contains(&c);
+        let in_image = radius.is_finite()
+            && radius <= width.max(height) as f32
+            && near(x, width)
+            && near(y, height);
+        if !in_image {
+            return None;
+        }
+
+        let (mut sum, mut count) = (0.0, 0);
+        let reach = radius.ceil() as isize;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (px, py) = (x.floor() as isize + dx, y.floor() as isize + dy);
+                if (dx * dx + dy * dy) as f32 > radius * radius || px < 0 || py < 0 {
+                    continue;
+                }
+                if let Some(value) = image.get(px as usize, py as usize) {
+                    sum += value as f32 / 255.0;
+                    count += 1;
+                }
+            }
+        }
+        (count > 0).then(|| sum / count as f32)
+    };
+
+    let mut modules = vec![0.0; dimension * dimension];
+    for y in 0..dimension {
+        for x in 0..dimension {
+            modules[y * dimension + x] = reflectance(x as f32 + 0.5, y as f32 + 0.5)
+                .ok_or("the code runs off the edge of the image")?;
+        }
+    }
+
+    // the quiet zone counts towards the lightest reflectance
+    let size = dimension as f32;
+    let quiet_zone = (0..dimension).flat_map(|i| {
+        let i = i as f32 + 0.5;
+        [(i, -0.5), (i, size + 0.5), (-0.5, i), (size + 0.5, i)]
+    });
+    let (mut r_max, mut r_min) = (0f32, 1f32);
+    for r in modules
+        .iter()
+        .copied()
+        .chain(quiet_zone.filter_map(|(u, v)| reflectance(u, v)))
+    {
+        r_max = r_max.max(r);
+        r_min = r_min.min(r);
+    }
+    let symbol_contrast = r_max - r_min;
+    let threshold = (r_max + r_min) / 2.0;
+
+    let mut grid = image::Bitmap::new(dimension, dimension);
+    for y in 0..dimension {
+        for x in 0..dimension {
+            grid.set_bit(x, y, modules[y * dimension + x] < threshold);
+        }
+    }
+    let decoded = decode::read_qr(&grid);
+
+    let modulation_of = |r: f32| {
+        let modulation = 2.0 * (r - threshold).abs() / symbol_contrast.max(f32::EPSILON);
+        Grade::at_least(modulation, [0.5, 0.4, 0.3, 0.2])
+    };
+
+    // every finder pattern (with its separator) and timing pattern is graded
+    // by how many of its modules are the wrong color
+    let expected = image::Bitmap::new_blank_qr(version);
+    let max = dimension - 1;
+    let mut segments = [0usize; 5];
+    for y in 0..dimension {
+        for x in 0..dimension {
+            let segment = match super::coord_status(x, y, version) {
+                Some(1) if x < 8 && y < 8 => 0,
+                Some(1) if y < 8 => 1,
+                Some(1) => 2,
+                Some(2) if y == 6 => 3,
+                Some(2) => 4,
+                _ => continue,
+            };
+            if grid.get_bit(x, y) != expected.get_bit(x, y)
+                || modulation_of(modules[y * dimension + x]) == Grade::F
+            {
+                segments[segment] += 1;
+            }
+        }
+    }
+    let fixed_pattern_damage_grade = segments
+        .iter()
+        .map(|&damage| Grade::at_most(damage as f32, [0.0, 1.0, 2.0, 3.0]))
+        .min()
+        .unwrap();
+
+    // the spacing of the modules along both axes, measured between the finders
+    let distance = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+    let span = size - 7.0;
+    let near = size - 3.5;
+    let width = (distance(transform(3.5, 3.5), transform(near, 3.5))
+        + distance(transform(3.5, near), transform(near, near)))
+        / (2.0 * span);
+    let height = (distance(transform(3.5, 3.5), transform(3.5, near))
+        + distance(transform(near, 3.5), transform(near, near)))
+        / (2.0 * span);
+    let axial_nonuniformity = (width - height).abs() / ((width + height) / 2.0);
+
+    // how far the modules are from where the finder patterns alone put them
+    let ideal = detect::affine_transform(&finders, dimension);
+    let mut grid_nonuniformity = 0f32;
+    for y in 0..dimension {
+        for x in 0..dimension {
+            let (u, v) = (x as f32 + 0.5, y as f32 + 0.5);
+            grid_nonuniformity = grid_nonuniformity.max(distance(transform(u, v), ideal(u, v)));
+        }
+    }
+    grid_nonuniformity /= (width + height) / 2.0;
+
+    let (unused_error_correction, modulation_grade) = match &decoded {
+        Ok(decoded) => {
+            let blocks = &decoded.health.blocks;
+            let unused = |erasures: &[usize]| {
+                blocks
+                    .iter()
+                    .zip(erasures)
+                    .map(|(block, &s)| {
+                        1.0 - (2 * block.errors + s) as f32 / block.ec_codewords as f32
+                    })
+                    .fold(1f32, f32::min)
+            };
+            let unused_error_correction = unused(&vec![0; blocks.len()]);
+
+            // the grade of every codeword is that of its worst module
+            let owners = codeword_blocks(version, decoded.level);
+            let mut codewords = vec![Grade::A; owners.len()];
+            let (mut x, mut y) = (max, max);
+            for bit in 0.. {
+                if let Some(codeword) = codewords.get_mut(bit / 8) {
+                    *codeword = (*codeword).min(modulation_of(modules[y * dimension + x]));
+                }
+                match super::next_data_bit(x, y, version) {
+                    Some(next) => (x, y) = next,
+                    None => break,
+                }
+            }
+
+            // at each grade, the codewords that don't make it count as erasures.
+            // the modulation grade is the best grade that still leaves enough
+            // error correction for that grade
+            let modulation_grade = [Grade::A, Grade::B, Grade::C, Grade::D]
+                .into_iter()
+                .map(|level| {
+                    let mut erasures = vec![0; blocks.len()];
+                    for (&grade, &block) in codewords.iter().zip(&owners) {
+                        erasures[block] += usize::from(grade < level);
+                    }
+                    let unused = unused(&erasures);
+                    if unused < 0.0 {
+                        Grade::F
+                    } else {
+                        level.min(unused_error_correction_grade(unused))
+                    }
+                })
+                .max()
+                .unwrap();
+            (unused_error_correction, modulation_grade)
+        }
+        Err(_) => (0.0, Grade::F),
+    };
+
+    let mut report = QualityReport {
+        decode: if decoded.is_ok() { Grade::A } else { Grade::F },
+        symbol_contrast,
+        symbol_contrast_grade: Grade::at_least(symbol_contrast, [0.7, 0.55, 0.4, 0.2]),
+        modulation_grade,
+        fixed_pattern_damage_grade,
+        axial_nonuniformity,
+        axial_nonuniformity_grade: Grade::at_most(axial_nonuniformity, [0.06, 0.08, 0.1, 0.12]),
+        grid_nonuniformity,
+        grid_nonuniformity_grade: Grade::at_most(grid_nonuniformity, [0.38, 0.5, 0.63, 0.75]),
+        unused_error_correction,
+        unused_error_correction_grade: unused_error_correction_grade(unused_error_correction),
+        overall: Grade::A,
+    };
+    report.overall = [
+        report.decode,
+        report.symbol_contrast_grade,
+        report.modulation_grade,
+        report.fixed_pattern_damage_grade,
+        report.axial_nonuniformity_grade,
+        report.grid_nonuniformity_grade,
+        report.unused_error_correction_grade,
+    ]
+    .into_iter()
+    .min()
+    .unwrap();
+    Ok(report)
+}
+
+fn unused_error_correction_grade(unused: f32) -> Grade {
+    Grade::at_least(unused, [0.62, 0.5, 0.37, 0.25])
+}
+
+// the block that each codeword belongs to, in the interleaved order
+// they're placed in the symbol (see full_block_decode)
+fn codeword_blocks(version: u32, level: u8) -> Vec<usize> {
+    let (block_count, codewords, data_codewords, optional) = tables::get_block_info(version, level);
+    let (block_count_2, _, data_codewords_2) = optional.unwrap_or((0, 0, 0));
+    let data_lengths: Vec<usize> = std::iter::repeat_n(data_codewords, block_count)
+        .chain(std::iter::repeat_n(data_codewords_2, block_count_2))
+        .collect();
+
+    let mut output = Vec::new();
+    for i in 0..*data_lengths.last().unwrap() {
+        for (block, &length) in data_lengths.iter().enumerate() {
+            if i < length {
+                output.push(block);
+            }
+        }
+    }
+    for _ in 0..codewords - data_codewords {
+        output.extend(0..data_lengths.len());
+    }
+    output
+}
+
+// a clean render gets an A, and a faded one doesn't
+#[test]
+fn grade_renders() -> Result<(), String> {
+    use super::badstream::{make_qr, QRInput};
+
+    let bitmap = make_qr(
+        QRInput::Auto("grade me".to_string()),
+        Some(8),
+        Some(1),
+        None,
+    )
+    .add_border()
+    .scale(Some(400));
+    let clean = image::GrayImage::from_bitmap(&bitmap);
+    let report = grade(&clean)?;
+    if report.overall != Grade::A || report.unused_error_correction != 1.0 {
+        return Err(format!("clean: {:?}", report));
+    }
+
+    // gray on lighter gray
+    let mut faded = clean.clone();
+    let (width, height) = faded.dims();
+    for y in 0..height {
+        for x in 0..width {
+            let value = clean.get(x, y).unwrap();
+            faded.set(x, y, if value < 128 { 110 } else { 180 });
+        }
+    }
+    let report = grade(&faded)?;
+    if report.symbol_contrast_grade != Grade::D || report.overall != Grade::D {
+        return Err(format!("faded: {:?}", report));
+    }
+    Ok(())
+}
@@ -0,0 +1,536 @@
+use super::{
+    badstream::{self, Badstream, QRInput},
+    bitstream::{search, Fnc1, Mode, Token},
+    image,
+    tables::VersionBlockInfo,
+};
+
+/*
+rectangular micro qr (rmqr, iso/iec 23941) codes are long and thin,
+for labels where a square code would waste space:
+1. there are 32 sizes, from 7 to 17 modules high and 27 to 139 wide,
+   numbered 0 to 31 in the order of VERSIONS
+2. there's a finder pattern in the top left, a smaller "finder sub pattern"
+   in the bottom right, and corner marks in the other two corners
+3. timing patterns run along all four edges, and down the middle of
+   the code through the alignment patterns
+4. the format information is 18 bits long and appears twice, once next to
+   each finder pattern, with a different mask on each copy
+5. only levels M and H exist, and there's only one mask
+6. mode indicators are 3 bits long, and count indicators depend on the size
+*/
+
+/// The sizes of rMQR code, in order of their version indicators:
+/// height, width, block layout at levels M and H (as in
+/// [VersionBlockInfo]), and character count indicator widths for
+/// the numeric, alphanumeric, ASCII and kanji modes respectively.
+#[rustfmt::skip]
+const VERSIONS: [(usize, usize, [VersionBlockInfo; 2], [usize; 4]); 32] = [
+    (7, 43, [(1, 13, 6, None), (1, 13, 3, None)], [4, 3, 3, 2]),
+    (7, 59, [(1, 21, 12, None), (1, 21, 7, None)], [5, 5, 4, 3]),
+    (7, 77, [(1, 32, 20, None), (1, 32, 10, None)], [6, 5, 5, 4]),
+    (7, 99, [(1, 44, 28, None), (1, 44, 14, None)], [7, 6, 5, 5]),
+    (7, 139, [(1, 68, 44, None), (2, 34, 12, None)], [7, 6, 6, 5]),
+    (9, 43, [(1, 21, 12, None), (1, 21, 7, None)], [5, 5, 4, 3]),
+    (9, 59, [(1, 33, 21, None), (1, 33, 11, None)], [6, 5, 5, 4]),
+    (9, 77, [(1, 49, 31, None), (1, 24, 8, Some((1, 25, 9)))], [7, 6, 5, 5]),
+    (9, 99, [(1, 66, 42, None), (2, 33, 11, None)], [7, 6, 6, 5]),
+    (9, 139, [(1, 49, 31, Some((1, 50, 32))), (3, 33, 11, None)], [8, 7, 6, 6]),
+    (11, 27, [(1, 15, 7, None), (1, 15, 5, None)], [4, 4, 3, 2]),
+    (11, 43, [(1, 31, 19, None), (1, 31, 11, None)], [6, 5, 5, 4]),
+    (11, 59, [(1, 47, 31, None), (1, 23, 7, Some((1, 24, 8)))], [7, 6, 5, 5]),
+    (11, 77, [(1, 67, 43, None), (1, 33, 11, Some((1, 34, 12)))], [7, 6, 6, 5]),
+    (11, 99, [(1, 44, 28, Some((1, 45, 29))), (1, 44, 14, Some((1, 45, 15)))], [8, 7, 6, 6]),
+    (11, 139, [(2, 66, 42, None), (3, 44, 14, None)], [8, 7, 7, 6]),
+    (13, 27, [(1, 21, 12, None), (1, 21, 7, None)], [5, 5, 4, 3]),
+    (13, 43, [(1, 41, 27, None), (1, 41, 13, None)], [6, 6, 5, 5]),
+    (13, 59, [(1, 60, 38, None), (2, 30, 10, None)], [7, 6, 6, 5]),
+    (13, 77, [(1, 42, 26, Some((1, 43, 27))), (1, 42, 14, Some((1, 43, 15)))], [7, 7, 6, 6]),
+    (13, 99, [(1, 56, 36, Some((1, 57, 37))), (1, 37, 11, Some((2, 38, 12)))], [8, 7, 7, 6]),
+    (13, 139, [(2, 55, 35, Some((1, 56, 36))), (2, 41, 13, Some((2, 42, 14)))], [8, 8, 7, 7]),
+    (15, 43, [(1, 51, 33, None), (1, 25, 7, Some((1, 26, 8)))], [7, 6, 6, 5]),
+    (15, 59, [(1, 74, 48, None), (2, 37, 13, None)], [7, 7, 6, 5]),
+    (15, 77, [(1, 51, 33, Some((1, 52, 34))), (2, 34, 10, Some((1, 35, 11)))], [8, 7, 7, 6]),
+    (15, 99, [(2, 68, 44, None), (4, 34, 12, None)], [8, 7, 7, 6]),
+    (15, 139, [(2, 66, 42, Some((1, 67, 43))), (1, 39, 13, Some((4, 40, 14)))], [9, 8, 7, 7]),
+    (17, 43, [(1, 61, 39, None), (1, 30, 10, Some((1, 31, 11)))], [7, 6, 6, 5]),
+    (17, 59, [(2, 44, 28, None), (2, 44, 14, None)], [8, 7, 6, 6]),
+    (17, 77, [(2, 61, 39, None), (1, 40, 12, Some((2, 41, 13)))], [8, 7, 7, 6]),
+    (17, 99, [(2, 53, 33, Some((1, 54, 34))), (4, 40, 14, None)], [8, 8, 7, 6]),
+    (17, 139, [(4, 58, 38, None), (2, 38, 12, Some((4, 39, 13)))], [9, 8, 8, 7]),
+];
+
+/// Make an rMQR code.
+///
+/// The code is the smallest one (by area) that fits the message, out
+/// of those with the given height and width, if any. rMQR codes only
+/// have levels M (the default) and H.
+pub fn make_rmqr(
+    input: QRInput,
+    height_choice: Option<usize>,
+    width_choice: Option<usize>,
+    level_choice: Option<u8>,
+) -> Result<image::Bitmap, String> {
+    let level = match level_choice {
+        None | Some(1) => 1,
+        Some(3) => 3,
+        _ => return Err("rMQR codes only have levels M and H".to_string()),
+    };
+
+    let mut sizes: Vec<usize> = (0..VERSIONS.len())
+        .filter(|&v| {
+            height_choice.is_none_or(|h| h == VERSIONS[v].0)
+                && width_choice.is_none_or(|w| w == VERSIONS[v].1)
+        })
+        .collect();
+    if sizes.is_empty() {
+        return Err("there's no rMQR code of that size".to_string());
+    }
+    sizes.sort_by_key(|&v| VERSIONS[v].0 * VERSIONS[v].1);
+
+    let mut error = String::new();
+    for version in sizes {
+        let (fnc1, segments) = input.clone().into_segments_for(segment_costs(version));
+        let tokens = badstream::segments_to_tokens(segments, fnc1);
+        match encode_symbol(&tokens, version, level) {
+            Ok(stream) => return Ok(stream_to_symbol(&stream, version, level)),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// What segments cost in an rMQR size, for [search::optimize_mode].
+pub fn segment_costs(version: usize) -> search::SegmentCosts {
+    search::SegmentCosts {
+        mode_bits: 3,
+        count_bits: VERSIONS[version].3,
+        fnc1: false,
+    }
+}
+
+/// The name of an rMQR size, such as `R7x43`.
+pub fn version_name(version: usize) -> String {
+    format!("R{}x{}", VERSIONS[version].0, VERSIONS[version].1)
+}
+
+/// Return the rMQR version (if any) based on a bitmap's dimensions.
+fn size_to_version(width: usize, height: usize) -> Option<usize> {
+    VERSIONS.iter().position(|v| (v.1, v.0) == (width, height))
+}
+
+/// Methods specific to rMQR codes.
+impl image::Bitmap {
+    /// Return the rMQR version (from 0 to 31) based on the bitmap's dimensions.
+    pub fn rmqr_version(&self) -> Option<usize> {
+        let (width, height) = self.dims();
+        size_to_version(width, height)
+    }
+}
+
+/// the columns that the alignment patterns (and the vertical
+/// timing patterns between them) are centered on.
+fn alignment_columns(width: usize) -> &'static [usize] {
+    match width {
+        43 => &[21],
+        59 => &[19, 39],
+        77 => &[25, 51],
+        99 => &[23, 49, 75],
+        139 => &[27, 55, 83, 111],
+        _ => &[],
+    }
+}
+
+/// returns the type of pixel taken up by a coordinate in an rmqr code.
+///
+/// from 0 to 4: data, position (finder patterns, separator and corners),
+/// timing, format, alignment
+pub fn coord_status(x: usize, y: usize, version: usize) -> Option<u8> {
+    let (height, width, ..) = VERSIONS[version];
+    if x >= width || y >= height {
+        return None;
+    }
+    let (max_x, max_y) = (width - 1, height - 1);
+    let alignment = alignment_columns(width);
+
+    Some(if x < 8 && y < 8 {
+        // finder pattern and separator
+        1
+    } else if max_x - x < 5 && max_y - y < 5 {
+        // finder sub pattern
+        1
+    } else if (x <= 2 && y == max_y) || (x <= 1 && y == max_y - 1) || (max_x - x <= 1 && y <= 1) {
+        // corner patterns. (in short codes the bottom left one runs
+        // into the finder pattern and separator)
+        1
+    } else if alignment
+        .iter()
+        .any(|&c| x.abs_diff(c) <= 1 && (y <= 2 || max_y - y <= 2))
+    {
+        // alignment patterns
+        4
+    } else if y == 0 || y == max_y || x == 0 || x == max_x || alignment.contains(&x) {
+        // timing patterns
+        2
+    } else if ((8..=10).contains(&x) && (1..=5).contains(&y))
+        || (x == 11 && (1..=3).contains(&y))
+        || ((max_x - 7..=max_x - 5).contains(&x) && (max_y - 5..max_y).contains(&y))
+        || ((max_x - 4..=max_x - 2).contains(&x) && y == max_y - 5)
+    {
+        // format information, next to both finder patterns
+        3
+    } else {
+        0
+    })
+}
+
+#[inline]
+pub fn coord_is_data(x: usize, y: usize, version: usize) -> bool {
+    coord_status(x, y, version).is_some_and(|c| c == 0)
+}
+
+/// the same zigzag as in a regular qr code. the rightmost column is all
+/// function patterns, so the walk starts one column in, at the bottom.
+pub fn next_data_bit(x: usize, y: usize, version: usize) -> Option<(usize, usize)> {
+    let (height, width, ..) = VERSIONS[version];
+    super::zigzag(x, y, (width - 2, height - 1), None, |x, y| {
+        coord_is_data(x, y, version)
+    })
+}
+
+fn new_blank_rmqr(version: usize) -> image::Bitmap {
+    let (height, width, ..) = VERSIONS[version];
+    let (max_x, max_y) = (width - 1, height - 1);
+    let mut output = image::Bitmap::new(width, height);
+
+    for x in 0..width {
+        for y in 0..height {
+            let dark = match coord_status(x, y, version) {
+                Some(1) => {
+                    if x < 8 && y < 8 {
+                        // finder pattern, with a light ring and separator
+                        let d = x.abs_diff(3).max(y.abs_diff(3));
+                        d != 2 && d != 4
+                    } else if max_x - x < 5 && max_y - y < 5 {
+                        // finder sub pattern, with a light ring
+                        (max_x - x).abs_diff(2).max((max_y - y).abs_diff(2)) != 1
+                    } else {
+                        // corners are dark, apart from the inside of the bend
+                        !((x, y) == (1, max_y - 1) || (x, y) == (max_x - 1, 1))
+                    }
+                }
+                Some(2) => {
+                    if y == 0 || y == max_y {
+                        x % 2 == 0
+                    } else {
+                        y % 2 == 0
+                    }
+                }
+                // alignment patterns have a light center
+                Some(4) => !alignment_columns(width).contains(&x) || (y != 1 && y != max_y - 1),
+                _ => false,
+            };
+            output.set_bit(x, y, dark);
+        }
+    }
+
+    output
+}
+
+// the bits of an rmqr code's data, before error correction
+fn tokens_to_badstream(tokens: &[Token], version: usize) -> Result<Badstream, String> {
+    let mut output: Badstream = Vec::new();
+    let push = |value: u32, width: usize, stream: &mut Badstream| {
+        for k in (0..width).rev() {
+            stream.push(u8::from(value & (1 << k) != 0));
+        }
+    };
+
+    for token in tokens {
+        match *token {
+            Token::ModeAndCount(mode, count) => {
+                let (indicator, index) = match mode {
+                    Mode::Numeric => (0b001, 0),
+                    Mode::AlphaNum => (0b010, 1),
+                    Mode::ASCII => (0b011, 2),
+                    Mode::Kanji => (0b100, 3),
+                };
+                let width = VERSIONS[version].3[index];
+                if count >> width != 0 {
+                    return Err(format!(
+                        "{} codes can't hold {} characters of {:?} data in one segment",
+                        version_name(version),
+                        count,
+                        mode
+                    ));
+                }
+                push(indicator, 3, &mut output);
+                push(count as u32, width, &mut output);
+            }
+            Token::Character(width, value) => push(value as u32, width, &mut output),
+            Token::EciChange(designator) => {
+                push(0b111, 3, &mut output);
+                // the designator is written the same way as in a qr code
+                match designator {
+                    0..=0x7F => push(designator, 8, &mut output),
+                    0x80..=0x3FFF => push(0b10 << 14 | designator, 16, &mut output),
+                    _ => push(0b110 << 21 | designator, 24, &mut output),
+                }
+            }
+            Token::Fnc1(Fnc1::First) => push(0b101, 3, &mut output),
+            Token::Fnc1(Fnc1::Second(indicator)) => {
+                push(0b110, 3, &mut output);
+                push(indicator as u32, 8, &mut output);
+            }
+            // rmqr has its own terminator
+            Token::Terminator => {}
+            Token::StructuredAppend(..) => {
+                return Err("rMQR codes can't be part of a Structured Append sequence".to_string())
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// The full stream of bits in an rMQR symbol, error correction included.
+fn encode_symbol(tokens: &[Token], version: usize, level: u8) -> Result<Badstream, String> {
+    let block_info = VERSIONS[version].2[usize::from(level == 3)];
+    let (block_count, _, data_codewords, optional) = block_info;
+    let data_bits = 8 * match optional {
+        Some((block_count_2, _, data_codewords_2)) => {
+            block_count * data_codewords + block_count_2 * data_codewords_2
+        }
+        None => block_count * data_codewords,
+    };
+
+    let mut stream = tokens_to_badstream(tokens, version)?;
+    if stream.len() > data_bits {
+        return Err(format!(
+            "{}-{} codes only fit {} bits, but the message takes {}",
+            version_name(version),
+            b"LMQH"[level as usize] as char,
+            data_bits,
+            stream.len()
+        ));
+    }
+
+    // the terminator is 3 zeros, or fewer if the symbol is full,
+    // then more zeros up to the end of the codeword
+    let terminator = 3.min(data_bits - stream.len());
+    stream.resize((stream.len() + terminator).next_multiple_of(8), 0);
+
+    Ok(badstream::encode_blocks(&stream, block_info))
+}
+
+fn stream_to_symbol(stream: &Badstream, version: usize, level: u8) -> image::Bitmap {
+    let (height, width, ..) = VERSIONS[version];
+    let mut bitmap = new_blank_rmqr(version);
+
+    let mut position = next_data_bit(width - 2, height - 1, version);
+    for &i in stream {
+        let (x, y) = position.expect("stream_to_symbol(): bitstream doesn't fit");
+        bitmap.set_bit(x, y, i != 0);
+        position = next_data_bit(x, y, version);
+    }
+
+    // there's only the one mask, which is the same as qr mask 4.
+    // the remainder bits after the stream get masked too
+    for x in 0..width {
+        for y in 0..height {
+            if coord_is_data(x, y, version) && super::mask_bit(4, x, y) {
+                bitmap.set_bit(x, y, !bitmap.get_bit(x, y).unwrap());
+            }
+        }
+    }
+
+    set_fcode(&mut bitmap, version, level);
+    bitmap
+}
+
+/// coordinates of both copies of the format information,
+/// from LSB (0) to MSB (17): next to the finder pattern,
+/// and next to the finder sub pattern.
+fn format_info_coords(version: usize, bit: usize) -> ((usize, usize), (usize, usize)) {
+    let (height, width, ..) = VERSIONS[version];
+    let coord1 = (8 + bit / 5, 1 + bit % 5);
+    let coord2 = match bit {
+        0..=14 => (width - 8 + bit / 5, height - 6 + bit % 5),
+        _ => (width - 20 + bit, height - 6),
+    };
+    (coord1, coord2)
+}
+
+/// the 18-bit format code: a level bit (M is 0, H is 1) and the
+/// version indicator, with the same bch code as a qr version code.
+fn generate_fcode(version: usize, level: u8) -> u32 {
+    super::qr_generate_vcode((u32::from(level == 3) << 5) | version as u32)
+}
+
+fn set_fcode(bitmap: &mut image::Bitmap, version: usize, level: u8) {
+    let fcode = generate_fcode(version, level);
+    let (mask1, mask2) = (0b01_1111_1010_1011_0010, 0b10_0000_1010_0111_1011);
+
+    for bit in 0..18 {
+        let ((x1, y1), (x2, y2)) = format_info_coords(version, bit);
+        bitmap.set_bit(x1, y1, (fcode ^ mask1) & (1 << bit) != 0);
+        bitmap.set_bit(x2, y2, (fcode ^ mask2) & (1 << bit) != 0);
+    }
+}
+
+#[test]
+fn rmqr_symbols() -> Result<(), String> {
+    // every size has exactly as many data modules as its codewords need,
+    // with fewer than 8 left over
+    for (version, &(height, width, blocks, _)) in VERSIONS.iter().enumerate() {
+        let modules = (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .filter(|&(x, y)| coord_is_data(x, y, version))
+            .count();
+        for (bc, cw, _, optional) in blocks {
+            let (bc2, cw2, _) = optional.unwrap_or((0, 0, 0));
+            if modules / 8 != bc * cw + bc2 * cw2 {
+                return Err(format!("{}: {} modules", version_name(version), modules));
+            }
+        }
+    }
+
+    for (text, height, level, version) in [
+        ("12345", None, None, 10),
+        ("Hello, world!", None, Some(3), 22),
+        ("https://example.com/", Some(17), None, 27),
+        ("Grüße 漢字", None, None, 11),
+        // only this small when mode switches are costed with rmqr's indicators
+        ("13808C6F5", None, None, 10),
+        ("V60bK5335175V", None, None, 16),
+    ] {
+        let bitmap = make_rmqr(QRInput::Auto(text.to_string()), height, None, level)?;
+        if bitmap.rmqr_version() != Some(version) {
+            return Err(format!("{text}: wrong size {:?}", bitmap.dims()));
+        }
+        let level = level.unwrap_or(1);
+
+        // both copies of the format information
+        let fcode = generate_fcode(version, level);
+        for bit in 0..18 {
+            let ((x1, y1), (x2, y2)) = format_info_coords(version, bit);
+            let (b1, b2) = (0x1FAB2 >> bit & 1 == 1, 0x20A7B >> bit & 1 == 1);
+            let expected = fcode & (1 << bit) != 0;
+            if bitmap.get_bit(x1, y1) != Some(expected ^ b1)
+                || bitmap.get_bit(x2, y2) != Some(expected ^ b2)
+            {
+                return Err(format!("{text}: format information bit {bit}"));
+            }
+        }
+
+        // the data, read back along the zigzag with the mask removed
+        let (height, width, ..) = VERSIONS[version];
+        let mut stream: Badstream = Vec::new();
+        let mut position = next_data_bit(width - 2, height - 1, version);
+        while let Some((x, y)) = position {
+            stream.push(u8::from(
+                bitmap.get_bit(x, y).unwrap() ^ super::mask_bit(4, x, y),
+            ));
+            position = next_data_bit(x, y, version);
+        }
+        let (fnc1, segments) =
+            QRInput::Auto(text.to_string()).into_segments_for(segment_costs(version));
+        let expected = encode_symbol(
+            &badstream::segments_to_tokens(segments, fnc1),
+            version,
+            level,
+        )?;
+        if !stream
+            .into_iter()
+            .take(expected.len())
+            .eq(expected.into_iter().map(|b| u8::from(b != 0)))
+        {
+            return Err(format!("{text}: data doesn't read back"));
+        }
+    }
+
+    if make_rmqr(QRInput::Auto("12345".into()), Some(8), None, None).is_ok()
+        || make_rmqr(QRInput::Auto("12345".into()), None, None, Some(0)).is_ok()
+        || make_rmqr(QRInput::Auto("a".repeat(200)), None, None, None).is_ok()
+    {
+        return Err("invalid rmqr code was made".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn rmqr_reference_codewords() -> Result<(), String> {
+    // "12345" in R11x27-M, worked out by hand: mode 001, count 0101,
+    // 123 and 45 in 10 and 7 bits, a 3-bit terminator and padding.
+    // the error correction was checked against a separate implementation
+    let (fnc1, segments) = QRInput::Auto("12345".into()).into_segments_for(segment_costs(10));
+    let stream = encode_symbol(&badstream::segments_to_tokens(segments, fnc1), 10, 1)?;
+    let codewords = badstream::badstream_to_polynomial(&stream);
+    let expected = [
+        0x2A, 0x3D, 0xAD, 0x00, 0xEC, 0x11, 0xEC, // data
+        0x08, 0xA1, 0xFF, 0x57, 0xD2, 0x8A, 0x3D, 0xCF, // error correction
+    ];
+    if !codewords.iter().eq(expected.iter()) {
+        return Err(format!("12345: codewords {:02X?}", codewords));
+    }
+    Ok(())
+}
+
+#[test]
+fn rmqr_reference_symbols() -> Result<(), String> {
+    // whole symbols, as drawn by a separate encoder written from the
+    // standard's layout rules (not from this file), for a short size and a
+    // tall one with alignment patterns and blocks of two lengths
+    for (text, height, width, level, grid) in [
+        (
+            "0123456789",
+            7,
+            43,
+            1,
+            "
+            #######.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.###
+            #.....#..#.##.#.##..#.#.#.###.##.#.##...#.#
+            #.###.#.#.###.##.#..#####...#.#..##########
+            #.###.#..##..##.#...#..##.###.#..#....#...#
+            #.###.#...#.....#.#####.###..#.##.##..#.#.#
+            #.....#.####.#...#..#.#.###.#.#.##.##.#...#
+            #######.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#####
+            ",
+        ),
+        (
+            "hello, rectangular world",
+            17,
+            77,
+            3,
+            "
+            #######.#.#.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.#.#.###
+            #.....#.....#...#.#.#..##.#.##.....#.########.#...#.####..##.##..######.###.#
+            #.###.#..#..#.#.#...##..###.##...#.###..#.#..#...#######......####.####....##
+            #.###.#....#..#.#.##.#.....#..##.##.###..##.###.###.##...#.##..##.#..#.#...#.
+            #.###.#.#.#######.#..#.###..##.......##....#...###.#.###########.......#.##.#
+            #.....#.#..##..#.#.###..#..#######.#..#.##.##.#####...#..#.#.##.###.#.#.#..#.
+            #######..####..#####..##.######.####.##.##..####.####.#..#..##...#.#####.#.##
+            ........###.###.##....##..##..#####.###..#.#...#....#.#######..##.#.#####..#.
+            #.#.#####.#..#..##..##.#.####.##...#.#..#.#..###.#.#.#..#####.#.......##.#.##
+            .#.#.##..#..#.#...##.##.#..#.##.#...###.##......###.##.#.#..##########.##.#..
+            #.###.###.###..##.#.#.##.##..#.##...##..##.##..#..###.##......#.##..#...###.#
+            .####...#.#.#...######.#..#.#.###..#.#..........##..#....#####...#.###..##.#.
+            #.#..#.##..#..##.#########...###.##.##..######..##.##...#..##.####.#....#####
+            ..#.#....##..#..#...##.##.###..#...##.##..#.#..##.#.###.##..##..#...#.###...#
+            ###.##..........##.#.#.#####..#.#.###.##.#.##.##.#######.##...###.###...#.#.#
+            #..#..#...####.###....#.#.###......#...##.###...###.##.##.#.##...#.###.##...#
+            ###.#.#.#.#.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.#.#.#.###.#.#.#.#.#.#.#.#.#.#####
+            ",
+        ),
+    ] {
+        let bitmap = make_rmqr(
+            QRInput::Auto(text.to_string()),
+            Some(height),
+            Some(width),
+            Some(level),
+        )?;
+        if bitmap.as_text() != image::Bitmap::from_text(grid)?.as_text() {
+            return Err(format!("{text}: symbol differs:\n{}", bitmap.as_text()));
+        }
+    }
+    Ok(())
+}
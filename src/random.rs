@@ -0,0 +1,47 @@
+//! A small pseudorandom number generator, for tests and simulations
+//! that have to come out the same every time they're run.
+//!
+//! This is [SplitMix64](https://prng.di.unimi.it/splitmix64.c): not suitable
+//! for anything secret, but fast, and good enough to scatter noise around.
+
+/// A seeded stream of pseudorandom numbers.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`. `bound` can't be zero.
+    #[cfg(test)]
+    pub fn below(&mut self, bound: usize) -> usize {
+        assert!(bound > 0, "empty range");
+        // the bias is at most bound / 2^64, which doesn't matter here
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn float(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// `true` with probability `p`.
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.float() < p
+    }
+
+    /// A random element of a slice, which can't be empty.
+    #[cfg(test)]
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
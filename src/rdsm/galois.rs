@@ -58,6 +58,23 @@ pub fn qr_generate_fcode(fmt: u8) -> Option<u16> {
     Some(((fmt as u16) << 10) | (qr_fcode_remainder((fmt as u32) << 10)) as u16)
 }
 
+/// Find the valid format code closest to a 15-bit word that may have been damaged.
+///
+/// Returns the 5 bits of format data along with the number of bits that had
+/// to be flipped to reach it. Any two format codes differ in at least 7 bits,
+/// so a word with up to 3 bit errors can be corrected reliably; anything further
+/// away than that is rejected. The mask `0x5412` must already be removed.
+pub fn qr_correct_fcode(fcode: u16) -> Option<(u8, u32)> {
+    // there are only 32 possibilities, so just check them all
+    (0..32)
+        .map(|fmt| {
+            let distance = (qr_generate_fcode(fmt).unwrap() ^ fcode).count_ones();
+            (fmt, distance)
+        })
+        .min_by_key(|&(_, distance)| distance)
+        .filter(|&(_, distance)| distance <= 3)
+}
+
 #[inline]
 pub fn bit_length(n: BigElement) -> u32 {
     match n.checked_ilog2() {
@@ -22,6 +22,7 @@ pub fn polynomial_add(poly1: &Polynomial, poly2: &Polynomial) -> Polynomial {
 }
 
 /// An original polynomial multiplication algorithm.
+#[allow(clippy::needless_range_loop)]
 pub fn es_polynomial_multiply(poly1: &Polynomial, poly2: &Polynomial) -> Polynomial {
     let mut output: Polynomial = Vec::new();
     let (deg1, deg2) = (poly1.len() - 1, poly2.len() - 1);
@@ -131,9 +132,354 @@ pub fn encode_message(message: &Polynomial, ec_symbols: u32) -> Polynomial {
         };
 
     let mut message_padded = message.clone();
-    message_padded.extend(std::iter::repeat(0).take(ec_symbols as usize));
+    message_padded.extend(std::iter::repeat_n(0, ec_symbols as usize));
 
     let remainder = polynomial_remainder(&message_padded, &generator_polynomial);
 
     polynomial_add(&message_padded, &remainder)
 }
+
+/// Evaluate a polynomial at a point, using Horner's method.
+pub fn polynomial_eval(poly: &Polynomial, x: galois::Element) -> galois::Element {
+    let mut output = 0;
+    for &coefficient in poly {
+        output = galois::table_multiply(output, x) ^ coefficient;
+    }
+    output
+}
+
+/// Multiply every coefficient of a polynomial by a constant.
+pub fn polynomial_scale(poly: &Polynomial, x: galois::Element) -> Polynomial {
+    poly.iter().map(|&c| galois::table_multiply(c, x)).collect()
+}
+
+/// The syndromes of a received message, `S_0` to `S_(ec_symbols - 1)`.
+///
+/// The generator polynomials have the roots `1, a, a^2, ...`, so every
+/// syndrome of an undamaged message is zero. Note that unlike a
+/// [Polynomial], the syndromes are ordered from lowest index to highest.
+pub fn syndromes(received: &Polynomial, ec_symbols: u32) -> Vec<galois::Element> {
+    (0..ec_symbols as usize)
+        .map(|j| polynomial_eval(received, galois::exp(j)))
+        .collect()
+}
+
+/// The Berlekamp-Massey algorithm: find the shortest linear recurrence
+/// that generates the syndromes, i.e. the error locator polynomial.
+///
+/// The locator is `(1 + X_1 x)(1 + X_2 x)...`, where `X_k = a^p` for
+/// an error at power `p` of the received message.
+fn error_locator(syndromes: &[galois::Element]) -> Polynomial {
+    // both polynomials are ordered from highest power to lowest,
+    // so the coefficient of x^j is found at locator[len - 1 - j]
+    let mut locator: Polynomial = vec![1];
+    let mut previous: Polynomial = vec![1];
+
+    for k in 0..syndromes.len() {
+        // the discrepancy between the syndrome and what the
+        // current locator predicts it should be
+        let mut delta = syndromes[k];
+        for j in 1..locator.len() {
+            delta ^= galois::table_multiply(locator[locator.len() - 1 - j], syndromes[k - j]);
+        }
+
+        // shift the previous locator up by one power of x
+        previous.push(0);
+
+        if delta != 0 {
+            if previous.len() > locator.len() {
+                // the recurrence has to grow longer
+                let new_locator = polynomial_scale(&previous, delta);
+                previous = polynomial_scale(&locator, galois::table_divide(1, delta));
+                locator = new_locator;
+            }
+            locator = polynomial_add(&locator, &polynomial_scale(&previous, delta));
+        }
+    }
+
+    let lead = leading_zeros(&locator);
+    locator[lead..].to_vec()
+}
+
+/// Chien search: the vector indices of a received message of length
+/// `length` where `locator` has its roots.
+///
+/// Returns `None` if the roots don't all lie within the message, in which
+/// case there are more errors than the code can handle.
+fn error_positions(locator: &Polynomial, length: usize) -> Option<Vec<usize>> {
+    let mut output = Vec::new();
+    for power in 0..length {
+        // X = a^power is a root of the reversed locator iff
+        // a^-power is a root of the locator itself
+        if polynomial_eval(locator, galois::exp(255 - power % 255)) == 0 {
+            output.push(length - 1 - power);
+        }
+    }
+
+    if output.len() + 1 == locator.len() {
+        output.reverse();
+        Some(output)
+    } else {
+        None
+    }
+}
+
+/// Forney's algorithm: the values that have to be added to the
+/// received message at each error position to correct it.
+fn error_magnitudes(
+    syndromes: &[galois::Element],
+    locator: &Polynomial,
+    positions: &[usize],
+    length: usize,
+) -> Option<Vec<galois::Element>> {
+    // the evaluator polynomial, S(x) * locator(x) mod x^(ec_symbols)
+    let syndrome_poly: Polynomial = syndromes.iter().rev().copied().collect();
+    let product = es_polynomial_multiply(&syndrome_poly, locator);
+    let evaluator: Polynomial = product[product.len() - syndromes.len()..].to_vec();
+
+    let mut output = Vec::new();
+    for &position in positions {
+        let x = galois::exp(length - 1 - position);
+        let x_inverse = galois::table_divide(1, x);
+
+        // the formal derivative of the locator. over GF(2^8),
+        // the terms of even degree cancel out entirely
+        let mut derivative = 0;
+        for (i, &coefficient) in locator.iter().rev().enumerate() {
+            if i % 2 == 1 {
+                derivative ^=
+                    galois::table_multiply(coefficient, galois::table_pow(x_inverse, i as u32 - 1));
+            }
+        }
+        if derivative == 0 {
+            return None;
+        }
+
+        let numerator = galois::table_multiply(x, polynomial_eval(&evaluator, x_inverse));
+        output.push(galois::table_divide(numerator, derivative));
+    }
+    Some(output)
+}
+
+/// The main Reed-Solomon decoding function, the inverse of [encode_message].
+///
+/// Takes a received block of data and error-correcting codewords, and
+/// returns the corrected data codewords along with the indices of every
+/// codeword that had to be corrected. At most `ec_symbols / 2` errors
+/// can be corrected; any more than that, and the block is reported as
+/// uncorrectable (or, rarely, "corrected" to the wrong message).
+///
+/// If some of the damaged codewords are known in advance, use
+/// [decode_message_with_erasures] instead.
+pub fn decode_message(
+    received: &Polynomial,
+    ec_symbols: u32,
+) -> Result<(Polynomial, Vec<usize>), String> {
+    decode_message_with_erasures(received, ec_symbols, &[])
+}
+
+/// Reed-Solomon decoding with erasures.
+///
+/// An erasure is a codeword whose position is known to be unreliable,
+/// given as an index into `received`. Since only its value has to be found,
+/// an erasure costs half as much as an unknown error to correct: a block
+/// with `e` errors and `s` erasures can be corrected if `2e + s <= ec_symbols`.
+///
+/// The returned indices only include the codewords that were actually changed,
+/// so an erasure that turned out to hold the right value is left out.
+pub fn decode_message_with_erasures(
+    received: &Polynomial,
+    ec_symbols: u32,
+    erasures: &[usize],
+) -> Result<(Polynomial, Vec<usize>), String> {
+    let length = received.len();
+    let ec = ec_symbols as usize;
+    if ec > length || length > 255 {
+        return Err(format!(
+            "can't decode a block of {} codewords with {} error-correcting codewords",
+            length, ec
+        ));
+    }
+
+    let mut erasures = erasures.to_vec();
+    erasures.sort();
+    erasures.dedup();
+    if erasures.last().is_some_and(|&e| e >= length) {
+        return Err(format!(
+            "erasure out of bounds of {}-codeword block",
+            length
+        ));
+    }
+    if erasures.len() > ec {
+        return Err(format!(
+            "uncorrectable: {} erasures but only {} error-correcting codewords",
+            erasures.len(),
+            ec
+        ));
+    }
+
+    let syndromes = syndromes(received, ec_symbols);
+    if syndromes.iter().all(|&s| s == 0) {
+        // nothing to correct
+        return Ok((received[..length - ec].to_vec(), vec![]));
+    }
+
+    let uncorrectable = || Err("uncorrectable: too many errors in block".to_string());
+
+    // the erasure locator, (1 + X_1 x)(1 + X_2 x)..., built from the known positions
+    let mut erasure_locator: Polynomial = vec![1];
+    // the "forney syndromes", which have the erasures' contributions cancelled out
+    let mut forney_syndromes = syndromes.clone();
+    for &erasure in &erasures {
+        let x = galois::exp(length - 1 - erasure);
+        erasure_locator = es_polynomial_multiply(&erasure_locator, &vec![x, 1]);
+        for j in 0..(forney_syndromes.len() - 1) {
+            forney_syndromes[j] =
+                galois::table_multiply(forney_syndromes[j], x) ^ forney_syndromes[j + 1];
+        }
+    }
+
+    // the remaining syndromes only see the unknown errors
+    let locator = error_locator(&forney_syndromes[..ec - erasures.len()]);
+    if 2 * (locator.len() - 1) + erasures.len() > ec {
+        return uncorrectable();
+    }
+
+    // the errata locator has roots at both the errors and the erasures
+    let locator = es_polynomial_multiply(&locator, &erasure_locator);
+    let Some(positions) = error_positions(&locator, length) else {
+        return uncorrectable();
+    };
+    let Some(magnitudes) = error_magnitudes(&syndromes, &locator, &positions, length) else {
+        return uncorrectable();
+    };
+
+    let mut corrected = received.clone();
+    let mut changed = Vec::new();
+    for (&position, &magnitude) in positions.iter().zip(&magnitudes) {
+        if magnitude != 0 {
+            corrected[position] ^= magnitude;
+            changed.push(position);
+        }
+    }
+
+    // make sure the corrected message is actually a codeword
+    if self::syndromes(&corrected, ec_symbols)
+        .iter()
+        .any(|&s| s != 0)
+    {
+        return uncorrectable();
+    }
+
+    corrected.truncate(length - ec);
+    Ok((corrected, changed))
+}
+
+// damages encoded messages for every standard generator polynomial,
+// and checks that the damage is undone
+#[test]
+fn decode_round_trip() -> Result<(), String> {
+    for ec_symbols in [7, 10, 13, 15, 16, 17, 18, 20, 22, 24, 26, 28, 30, 68] {
+        let message: Polynomial = (0..40).map(|i: u32| (i * 37 + ec_symbols) as u8).collect();
+        let encoded = encode_message(&message, ec_symbols);
+
+        for errors in 0..=(ec_symbols as usize / 2) {
+            let mut damaged = encoded.clone();
+            let mut expected = Vec::new();
+            for e in 0..errors {
+                let index = 3 * e + 1;
+                damaged[index] ^= (e as u8).wrapping_mul(29) | 1;
+                expected.push(index);
+            }
+            expected.sort();
+
+            let (decoded, positions) = decode_message(&damaged, ec_symbols)?;
+            if decoded != message || positions != expected {
+                return Err(format!("ec {}, {} errors: bad decode", ec_symbols, errors));
+            }
+        }
+    }
+    Ok(())
+}
+
+// fills blocks to capacity with a mix of erasures and errors
+#[test]
+fn erasure_round_trip() -> Result<(), String> {
+    for ec_symbols in [10, 17, 30] {
+        let message: Polynomial = (0..30).map(|i: u32| (i * 53 + 1) as u8).collect();
+        let encoded = encode_message(&message, ec_symbols);
+        let ec = ec_symbols as usize;
+
+        for errors in 0..=(ec / 2) {
+            let erasure_count = ec - 2 * errors;
+            let mut damaged = encoded.clone();
+
+            // erasures at the start of the block, errors at the end
+            let erasures: Vec<usize> = (0..erasure_count).collect();
+            for &index in &erasures {
+                damaged[index] = 0;
+            }
+            for e in 0..errors {
+                damaged[encoded.len() - 1 - 2 * e] ^= 0x5A;
+            }
+
+            let (decoded, _) = decode_message_with_erasures(&damaged, ec_symbols, &erasures)?;
+            if decoded != message {
+                return Err(format!(
+                    "ec {}, {} errors, {} erasures: bad decode",
+                    ec_symbols, errors, erasure_count
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// one error, or one erasure, past what the error correction can fix.
+// a block like that can be miscorrected into a different message, but
+// none of these happen to be
+#[test]
+fn decode_past_capacity() -> Result<(), String> {
+    for ec_symbols in [7, 10, 17, 30] {
+        let message: Polynomial = (0..40).map(|i: u32| (i * 41 + ec_symbols) as u8).collect();
+        let encoded = encode_message(&message, ec_symbols);
+        let ec = ec_symbols as usize;
+
+        // ec / 2 + 1 errors, spread over the block
+        let mut damaged = encoded.clone();
+        let spacing = encoded.len() / (ec / 2 + 1);
+        for e in 0..=(ec / 2) {
+            damaged[spacing * e + 1] ^= (e as u8).wrapping_mul(71) | 1;
+        }
+        if decode_message(&damaged, ec_symbols).is_ok() {
+            return Err(format!("ec {}: {} errors decoded", ec_symbols, ec / 2 + 1));
+        }
+
+        // more erasures than error-correcting codewords
+        let erasures: Vec<usize> = (0..=ec).collect();
+        if decode_message_with_erasures(&encoded, ec_symbols, &erasures).is_ok() {
+            return Err(format!("ec {}: {} erasures decoded", ec_symbols, ec + 1));
+        }
+
+        // 2e + s = ec + 1, for every split between errors and erasures
+        for errors in 1..=(ec / 2) {
+            let erasures: Vec<usize> = (0..ec + 1 - 2 * errors).collect();
+            let mut damaged = encoded.clone();
+            for &index in &erasures {
+                damaged[index] ^= 0x33;
+            }
+            for e in 0..errors {
+                damaged[encoded.len() - 1 - 3 * e] ^= 0x5A;
+            }
+            if decode_message_with_erasures(&damaged, ec_symbols, &erasures).is_ok() {
+                return Err(format!(
+                    "ec {}, {} errors, {} erasures: decoded",
+                    ec_symbols,
+                    errors,
+                    erasures.len()
+                ));
+            }
+        }
+    }
+    Ok(())
+}